@@ -1,11 +1,13 @@
 mod pam;
+mod relogin;
 pub mod utmpx;
 
 use ::pam::{Authenticator, PasswordConv};
 use log::info;
 
-use crate::auth::pam::open_session;
 pub use crate::auth::pam::AuthenticationError;
+use crate::auth::pam::{authenticate, open_session};
+pub use crate::auth::relogin::RememberedLogin;
 
 pub struct AuthUserInfo<'a> {
     // This is used to keep the user session. If the struct is dropped then the user session is
@@ -39,11 +41,24 @@ pub fn try_auth<'a>(
             shell: entry.shell,
         })
         .map_err(|err| {
-            info!(
-                "Authentication failed for '{}'. Reason: {}",
-                username,
-                err.to_string()
-            );
+            info!("Authentication failed for '{}'. Reason: {}", username, err);
             err
         })
 }
+
+/// Verify the password of a user with PAM, e.g. to unlock the screen of a running session
+pub fn verify_password(
+    username: &str,
+    password: &str,
+    pam_service: &str,
+) -> Result<(), AuthenticationError> {
+    info!("Verifying the password of '{username}'");
+
+    authenticate(username, password, pam_service).map_err(|err| {
+        info!(
+            "Password verification failed for '{}'. Reason: {}",
+            username, err
+        );
+        err
+    })
+}
@@ -1,28 +1,266 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
 use log::info;
 
 use pam::{Authenticator, PasswordConv};
+use pam_sys::PamReturnCode;
 use pgs_files::passwd::{get_entry_by_name, PasswdEntry};
 
+/// The folders in which PAM looks for the file of a service. The first one takes precedence.
+const PAM_SERVICE_FOLDERS: [&str; 2] = ["/etc/pam.d", "/usr/lib/pam.d"];
+
+/// An error code returned by PAM
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PamError {
+    code: PamReturnCode,
+}
+
+impl From<pam::PamError> for PamError {
+    fn from(err: pam::PamError) -> Self {
+        // The pam crate does not expose the return code, only its name. The name is looked up among
+        // all return codes once, so that everything else matches on the code itself.
+        let name = format!("{err:?}");
+        let code = (PamReturnCode::SUCCESS as i32..=PamReturnCode::INCOMPLETE as i32)
+            .map(PamReturnCode::from)
+            .find(|code| format!("{code:?}") == name)
+            .unwrap_or(PamReturnCode::SYSTEM_ERR);
+
+        Self { code }
+    }
+}
+
+impl PamError {
+    /// A description of the return code, based on the PAM documentation
+    pub fn description(&self) -> &'static str {
+        match self.code {
+            PamReturnCode::OPEN_ERR => "Failed to load a PAM module",
+            PamReturnCode::SYMBOL_ERR => "Symbol not found in a PAM module",
+            PamReturnCode::SERVICE_ERR => "Error in a PAM module",
+            PamReturnCode::SYSTEM_ERR => "System error",
+            PamReturnCode::BUF_ERR => "Memory buffer error",
+            PamReturnCode::PERM_DENIED => "Permission denied",
+            PamReturnCode::AUTH_ERR => "Invalid login credentials",
+            PamReturnCode::CRED_INSUFFICIENT => {
+                "Insufficient credentials to access authentication data"
+            }
+            PamReturnCode::AUTHINFO_UNAVAIL => {
+                "Authentication service cannot retrieve authentication information"
+            }
+            PamReturnCode::USER_UNKNOWN => "Unknown user",
+            PamReturnCode::MAXTRIES => "Maximum number of login attempts reached",
+            PamReturnCode::NEW_AUTHTOK_REQD => "The password has expired and needs to be changed",
+            PamReturnCode::ACCT_EXPIRED => "The user account has expired",
+            PamReturnCode::SESSION_ERR => "Cannot create the session",
+            PamReturnCode::CRED_UNAVAIL => "Cannot retrieve the user credentials",
+            PamReturnCode::CRED_EXPIRED => "The user credentials have expired",
+            PamReturnCode::CRED_ERR => "Failed to set the user credentials",
+            PamReturnCode::NO_MODULE_DATA => "No module specific data is present",
+            PamReturnCode::CONV_ERR => "Conversation error",
+            PamReturnCode::AUTHTOK_ERR => "Authentication token manipulation error",
+            PamReturnCode::AUTHTOK_RECOVERY_ERR => "Authentication information cannot be recovered",
+            PamReturnCode::AUTHTOK_LOCK_BUSY => "Authentication token lock busy",
+            PamReturnCode::AUTHTOK_DISABLE_AGING => "Authentication token aging disabled",
+            PamReturnCode::TRY_AGAIN => "Preliminary check by the password service failed",
+            PamReturnCode::ABORT => "Critical error in a PAM module",
+            PamReturnCode::AUTHTOK_EXPIRED => "The authentication token has expired",
+            PamReturnCode::MODULE_UNKNOWN => "Unknown PAM module",
+            PamReturnCode::BAD_ITEM => "Bad item passed to PAM",
+            _ => "Unknown PAM error",
+        }
+    }
+}
+
+impl From<PamReturnCode> for PamError {
+    fn from(code: PamReturnCode) -> Self {
+        Self { code }
+    }
+}
+
+impl PamError {
+    /// Whether `pam_acct_mgmt` refused the account, rather than `pam_authenticate` refusing the
+    /// credentials
+    fn is_account_error(&self) -> bool {
+        matches!(
+            self.code,
+            PamReturnCode::ACCT_EXPIRED
+                | PamReturnCode::NEW_AUTHTOK_REQD
+                | PamReturnCode::PERM_DENIED
+        )
+    }
+
+    /// Whether `pam_setcred` failed, rather than `pam_open_session`
+    fn is_credentials_error(&self) -> bool {
+        matches!(
+            self.code,
+            PamReturnCode::CRED_INSUFFICIENT
+                | PamReturnCode::CRED_UNAVAIL
+                | PamReturnCode::CRED_EXPIRED
+                | PamReturnCode::CRED_ERR
+        )
+    }
+
+    /// Whether PAM itself failed, e.g. because a module could not be loaded. Then the credentials
+    /// were never checked.
+    fn is_system_error(&self) -> bool {
+        matches!(
+            self.code,
+            PamReturnCode::OPEN_ERR
+                | PamReturnCode::SYMBOL_ERR
+                | PamReturnCode::SERVICE_ERR
+                | PamReturnCode::SYSTEM_ERR
+                | PamReturnCode::BUF_ERR
+                | PamReturnCode::ABORT
+                | PamReturnCode::MODULE_UNKNOWN
+        )
+    }
+}
+
+impl Display for PamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?})", self.description(), self.code)
+    }
+}
+
+impl Error for PamError {}
+
 /// All the different errors that can occur during PAM opening an authenticated session
-#[derive(Clone)]
+///
+/// Every phase of the PAM login has its own variant, so that e.g. an expired account is reported
+/// differently from a wrong password.
+#[derive(Clone, Debug)]
 pub enum AuthenticationError {
-    PamService(String),
-    AccountValidation,
+    PamService(String, PamError),
+    /// The file of the PAM service does not exist
+    MissingService(String),
+    /// PAM failed before it could check the credentials, e.g. because a module is missing
+    Unavailable(PamError),
+    /// `pam_authenticate` refused the credentials
+    Authentication(PamError),
+    /// `pam_acct_mgmt` refused the account, e.g. because it expired
+    AccountValidation(PamError),
     UsernameNotFound,
-    SessionOpen,
+    /// `pam_setcred` failed to establish the credentials, e.g. Kerberos tickets
+    Credentials(PamError),
+    SessionOpen(PamError),
 }
 
-impl ToString for AuthenticationError {
-    fn to_string(&self) -> String {
+impl AuthenticationError {
+    /// Whether logging in failed because PAM is not set up correctly, rather than because of the
+    /// credentials. Only an administrator can fix this.
+    pub fn is_misconfiguration(&self) -> bool {
+        matches!(
+            self,
+            Self::PamService(..) | Self::MissingService(_) | Self::Unavailable(_)
+        )
+    }
+
+    /// The pam crate runs `pam_authenticate` and `pam_acct_mgmt` together, so the phase that failed
+    /// is derived from the return code.
+    fn from_authenticate(err: pam::PamError) -> Self {
+        let err = PamError::from(err);
+        if err.is_system_error() {
+            Self::Unavailable(err)
+        } else if err.is_account_error() {
+            Self::AccountValidation(err)
+        } else {
+            Self::Authentication(err)
+        }
+    }
+
+    /// The pam crate runs `pam_setcred` and `pam_open_session` together, so the phase that failed
+    /// is derived from the return code.
+    fn from_open_session(err: pam::PamError) -> Self {
+        let err = PamError::from(err);
+        if err.is_system_error() {
+            Self::Unavailable(err)
+        } else if err.is_credentials_error() {
+            Self::Credentials(err)
+        } else {
+            Self::SessionOpen(err)
+        }
+    }
+}
+
+impl Display for AuthenticationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
-            AuthenticationError::PamService(service) => format!("Failed to create authenticator with PAM service '{service}'"),
-            AuthenticationError::AccountValidation => "Invalid login credentials".to_string(),
-            AuthenticationError::UsernameNotFound => "Login creditionals are valid, but username is not found. This should not be possible :(".to_string(),
-            AuthenticationError::SessionOpen => "Failed to open a PAM session".to_string(),
+            Self::PamService(service, err) => write!(
+                f,
+                "Failed to create authenticator with PAM service '{service}': {err}"
+            ),
+            Self::MissingService(service) => write!(
+                f,
+                "The PAM service '{service}' does not exist in {}",
+                PAM_SERVICE_FOLDERS.join(" or ")
+            ),
+            Self::Unavailable(err) => write!(f, "PAM is unavailable: {err}"),
+            Self::Authentication(err) => write!(f, "Failed to authenticate: {err}"),
+            Self::AccountValidation(err) => write!(f, "Failed to validate the account: {err}"),
+            Self::Credentials(err) => write!(f, "Failed to establish the credentials: {err}"),
+            Self::UsernameNotFound => f.write_str(
+                "Login creditionals are valid, but username is not found. This should not be possible :(",
+            ),
+            Self::SessionOpen(err) => write!(f, "Failed to open a PAM session: {err}"),
         }
     }
 }
 
+impl Error for AuthenticationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::PamService(_, err)
+            | Self::Unavailable(err)
+            | Self::Authentication(err)
+            | Self::AccountValidation(err)
+            | Self::Credentials(err)
+            | Self::SessionOpen(err) => Some(err),
+            Self::MissingService(_) | Self::UsernameNotFound => None,
+        }
+    }
+}
+
+/// Fail early if the PAM service does not exist
+///
+/// PAM would otherwise use the `other` service instead, which usually refuses every login. That
+/// looks exactly like a wrong password.
+fn check_service_exists(pam_service: &str) -> Result<(), AuthenticationError> {
+    // Without the first folder, PAM uses `/etc/pam.conf` instead
+    if !Path::new(PAM_SERVICE_FOLDERS[0]).is_dir() {
+        return Ok(());
+    }
+
+    let exists = PAM_SERVICE_FOLDERS
+        .iter()
+        .any(|folder| Path::new(folder).join(pam_service).is_file());
+    if exists {
+        Ok(())
+    } else {
+        Err(AuthenticationError::MissingService(pam_service.to_string()))
+    }
+}
+
+/// Only verify the password of a user, without opening a session
+pub fn authenticate(
+    username: &str,
+    password: &str,
+    pam_service: &str,
+) -> Result<(), AuthenticationError> {
+    check_service_exists(pam_service)?;
+
+    let mut authenticator = Authenticator::with_password(pam_service)
+        .map_err(|err| AuthenticationError::PamService(pam_service.to_string(), err.into()))?;
+
+    authenticator
+        .get_handler()
+        .set_credentials(username, password);
+
+    authenticator
+        .authenticate()
+        .map_err(AuthenticationError::from_authenticate)
+}
+
 /// Open a PAM authenticated session
 pub fn open_session<'a>(
     username: &str,
@@ -34,8 +272,10 @@ pub fn open_session<'a>(
 
     info!("Started opening session");
 
+    check_service_exists(pam_service)?;
+
     let mut authenticator = Authenticator::with_password(pam_service)
-        .map_err(|_| AuthenticationError::PamService(pam_service.to_string()))?;
+        .map_err(|err| AuthenticationError::PamService(pam_service.to_string(), err.into()))?;
 
     info!("Gotten Authenticator");
 
@@ -46,22 +286,82 @@ pub fn open_session<'a>(
 
     info!("Got handler");
 
-    // Validate the account
+    // Authenticate and validate the account
     authenticator
         .authenticate()
-        .map_err(|_| AuthenticationError::AccountValidation)?;
+        .map_err(AuthenticationError::from_authenticate)?;
 
     info!("Validated account");
 
     // NOTE: Maybe we should also load all groups here
     let passwd_entry = get_entry_by_name(&username).ok_or(AuthenticationError::UsernameNotFound)?;
 
+    // Establish the credentials and open the session
     authenticator
         .open_session()
-        .map_err(|_| AuthenticationError::SessionOpen)?;
+        .map_err(AuthenticationError::from_open_session)?;
 
     info!("Opened session");
 
     // NOTE: Logout happens automatically here with `drop` of session and context
     Ok((authenticator, passwd_entry))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pam_error_messages() {
+        let err = PamError::from(PamReturnCode::AUTH_ERR);
+        assert_eq!(err.to_string(), "Invalid login credentials (AUTH_ERR)");
+
+        let err = AuthenticationError::AccountValidation(PamReturnCode::ACCT_EXPIRED.into());
+        assert_eq!(
+            err.to_string(),
+            "Failed to validate the account: The user account has expired (ACCT_EXPIRED)"
+        );
+    }
+
+    #[test]
+    fn pam_error_codes() {
+        for code in [
+            PamReturnCode::AUTH_ERR,
+            PamReturnCode::NEW_AUTHTOK_REQD,
+            PamReturnCode::INCOMPLETE,
+        ] {
+            assert_eq!(PamError::from(pam::PamError::from(code)).code, code);
+        }
+    }
+
+    #[test]
+    fn pam_error_phases() {
+        let code = PamError::from;
+
+        assert!(code(PamReturnCode::ACCT_EXPIRED).is_account_error());
+        assert!(code(PamReturnCode::NEW_AUTHTOK_REQD).is_account_error());
+        assert!(!code(PamReturnCode::AUTH_ERR).is_account_error());
+
+        assert!(code(PamReturnCode::CRED_EXPIRED).is_credentials_error());
+        assert!(!code(PamReturnCode::SESSION_ERR).is_credentials_error());
+
+        assert!(code(PamReturnCode::OPEN_ERR).is_system_error());
+        assert!(code(PamReturnCode::MODULE_UNKNOWN).is_system_error());
+        assert!(!code(PamReturnCode::AUTH_ERR).is_system_error());
+    }
+
+    #[test]
+    fn pam_misconfiguration() {
+        let code = PamError::from;
+
+        assert!(AuthenticationError::MissingService("lemurs".to_string()).is_misconfiguration());
+        assert!(
+            AuthenticationError::Unavailable(code(PamReturnCode::OPEN_ERR)).is_misconfiguration()
+        );
+        assert!(
+            !AuthenticationError::Authentication(code(PamReturnCode::AUTH_ERR))
+                .is_misconfiguration()
+        );
+        assert!(!AuthenticationError::UsernameNotFound.is_misconfiguration());
+    }
+}
@@ -0,0 +1,40 @@
+use std::time::{Duration, Instant};
+
+/// The user that just logged out, who can login again without typing the password for a short
+/// time
+///
+/// No password is kept. Logging in again goes through a separate PAM service, which decides on its
+/// own whether the login is allowed without a password (e.g. with `pam_timestamp`).
+pub struct RememberedLogin {
+    username: String,
+    expires_at: Instant,
+}
+
+impl RememberedLogin {
+    pub fn new(username: &str, window: Duration) -> Self {
+        Self {
+            username: username.to_string(),
+            expires_at: Instant::now() + window,
+        }
+    }
+
+    /// Whether `username` can still login again without a password
+    pub fn allows(&self, username: &str) -> bool {
+        self.username == username && Instant::now() < self.expires_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remembered_login() {
+        let login = RememberedLogin::new("lemur", Duration::from_secs(60));
+        assert!(login.allows("lemur"));
+        assert!(!login.allows("other"));
+
+        let login = RememberedLogin::new("lemur", Duration::ZERO);
+        assert!(!login.allows("lemur"));
+    }
+}
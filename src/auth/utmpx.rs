@@ -78,6 +78,45 @@ pub fn add_utmpx_entry(username: &str, tty: u8, pid: u32) -> UtmpxSession {
     UtmpxSession(entry)
 }
 
+/// Whether the UTMPX records contain a running session for the user
+pub fn has_active_session(username: &str) -> bool {
+    let username = username.as_bytes();
+    let mut found = false;
+
+    unsafe {
+        libc::setutxent();
+
+        loop {
+            let entry = libc::getutxent();
+            if entry.is_null() {
+                break;
+            }
+            let entry = &*entry;
+
+            if entry.ut_type != libc::USER_PROCESS {
+                continue;
+            }
+
+            let user = entry
+                .ut_user
+                .iter()
+                .take_while(|c| **c != 0)
+                .map(|c| *c as u8)
+                .collect::<Vec<u8>>();
+
+            // Records of processes that are no longer running are stale
+            if user == username && libc::kill(entry.ut_pid, 0) == 0 {
+                found = true;
+                break;
+            }
+        }
+
+        libc::endutxent();
+    }
+
+    found
+}
+
 impl Drop for UtmpxSession {
     fn drop(&mut self) {
         let UtmpxSession(mut entry) = self;
@@ -18,14 +18,25 @@ const KB_84: u8 = 0x01;
 
 #[derive(Debug)]
 pub enum ChvtError {
-    Activate(i32),
-    WaitActive(i32),
+    Activate(Errno),
+    WaitActive(Errno),
     Close,
     OpenConsole,
     NotAConsole,
     GetFD,
 }
 
+impl ChvtError {
+    /// Whether the error was caused by missing privileges
+    pub fn is_permission_denied(&self) -> bool {
+        matches!(
+            self,
+            Self::Activate(Errno::EPERM | Errno::EACCES)
+                | Self::WaitActive(Errno::EPERM | Errno::EACCES)
+        )
+    }
+}
+
 impl Error for ChvtError {}
 impl Display for ChvtError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
@@ -92,17 +103,27 @@ fn get_fd() -> Result<i32, ChvtError> {
     Err(ChvtError::GetFD)
 }
 
+/// The number of the virtual terminal that lemurs was started on, if any
+pub fn current_vt() -> Option<u8> {
+    let path = nix::unistd::ttyname(0).ok()?;
+    path.to_str()?.strip_prefix("/dev/tty")?.parse().ok()
+}
+
 pub unsafe fn chvt(ttynum: i32) -> Result<(), ChvtError> {
     let fd = get_fd()?;
 
     let activate = unsafe { libc::ioctl(fd, VT_ACTIVATE, ttynum) };
-    if activate > 0 {
-        return Err(ChvtError::Activate(activate));
+    if activate < 0 {
+        let errno = Errno::last();
+        let _ = close(fd);
+        return Err(ChvtError::Activate(errno));
     }
 
     let wait = unsafe { libc::ioctl(fd, VT_WAITACTIVE, ttynum) };
-    if wait > 0 {
-        return Err(ChvtError::WaitActive(wait));
+    if wait < 0 {
+        let errno = Errno::last();
+        let _ = close(fd);
+        return Err(ChvtError::WaitActive(errno));
     }
 
     close(fd).map_err(|_| ChvtError::Close)?;
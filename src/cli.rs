@@ -1,8 +1,10 @@
 use std::env::args;
 use std::error::Error;
-use std::fmt::Display;
+use std::fmt::{Display, Write};
 use std::path::PathBuf;
 
+use crate::config::{Tty, TTY_RANGE};
+
 pub fn usage() {
     print!(
         r###"Lemurs {}
@@ -14,15 +16,31 @@ USAGE: lemurs [OPTIONS] [SUBCOMMAND]
 OPTIONS:
     -c, --config <FILE>    A file to replace the default configuration
     -h, --help             Print help information
+        --json             Print the output of subcommands and errors as JSON
+        --lock             Lock the terminal until the password of the current user is typed
+        --no-altscreen     Draw on the main screen instead of the alternate screen
         --no-log
         --preview
-        --tty <N>          Override the configured TTY number
+        --session <NAME>   Select this environment instead of the remembered one
+        --tty <N>          Override the configured TTY number or use 'auto'
     -V, --version          Print version information
 
 SUBCOMMANDS:
     cache
     envs
     help     Print this message or the help of the given subcommand(s)
+
+ENVIRONMENT:
+    LEMURS_CONFIG  The configuration file to use when `--config` is not given
+
+EXIT CODES:
+    0        Success
+    1        Unexpected failure
+    2        Invalid command line arguments
+    3        The configuration file cannot be loaded
+    4        Missing privileges (e.g. not ran as root)
+    5        Started within an existing session
+    6        The terminal UI cannot be started
 "###,
         env!("CARGO_PKG_VERSION"),
         env!("CARGO_PKG_AUTHORS")
@@ -32,8 +50,14 @@ SUBCOMMANDS:
 pub struct Cli {
     pub preview: bool,
     pub no_log: bool,
-    pub tty: Option<u8>,
+    pub no_altscreen: bool,
+    pub json: bool,
+    pub tty: Option<Tty>,
     pub config: Option<PathBuf>,
+    pub session: Option<String>,
+    /// The pipe to read login credentials from, for automated tests
+    #[cfg(feature = "insecure-test-credentials")]
+    pub test_credentials: Option<String>,
     pub command: Option<Commands>,
 }
 
@@ -42,6 +66,11 @@ pub enum Commands {
     Cache,
     Help,
     Version,
+    /// Only authenticate the given user with PAM. This is hidden from the usage, since it is only
+    /// meant for debugging PAM configurations.
+    TestAuth(String),
+    /// Show a lock screen for the user running lemurs, instead of the login form
+    Lock,
 }
 
 #[derive(Debug)]
@@ -58,7 +87,12 @@ impl Display for CliError {
                 write!(f, "Missing an argument for the given flag '{flag}'")
             }
             CliError::InvalidTTY => {
-                write!(f, "Given an invalid TTY number (only 1-12 are allowed)")
+                write!(
+                    f,
+                    "Given an invalid TTY number (only {}-{} or 'auto' are allowed)",
+                    TTY_RANGE.start(),
+                    TTY_RANGE.end()
+                )
             }
             CliError::InvalidArgument(arg) => {
                 write!(f, "Given an invalid flag or command '{arg}'")
@@ -74,8 +108,13 @@ impl Cli {
         let mut cli = Cli {
             preview: false,
             no_log: false,
+            no_altscreen: false,
+            json: false,
             tty: None,
             config: None,
+            session: None,
+            #[cfg(feature = "insecure-test-credentials")]
+            test_credentials: None,
             command: None,
         };
 
@@ -86,19 +125,38 @@ impl Cli {
                 (0, "cache") => cli.command = Some(Commands::Cache),
                 (0, "help") | (_, "--help") | (_, "-h") => cli.command = Some(Commands::Help),
                 (_, "--version") | (_, "-V") => cli.command = Some(Commands::Version),
+                (_, "--test-auth") => {
+                    let (_, arg) = args.next().ok_or(CliError::MissingArgument("test-auth"))?;
+                    cli.command = Some(Commands::TestAuth(arg));
+                }
 
+                (_, "--lock") => cli.command = Some(Commands::Lock),
                 (_, "--preview") => cli.preview = true,
                 (_, "--no-log") => cli.no_log = true,
+                (_, "--no-altscreen") => cli.no_altscreen = true,
+                (_, "--json") => cli.json = true,
                 (_, "--tty") => {
                     let (_, arg) = args.next().ok_or(CliError::MissingArgument("tty"))?;
-                    let arg = arg.parse().map_err(|_| CliError::InvalidTTY)?;
+                    let arg: Tty = arg.parse().map_err(|_| CliError::InvalidTTY)?;
 
-                    if arg == 0 || arg > 12 {
+                    if !arg.is_valid() {
                         return Err(CliError::InvalidTTY);
                     }
 
                     cli.tty = Some(arg);
                 }
+                (_, "--session") => {
+                    let (_, arg) = args.next().ok_or(CliError::MissingArgument("session"))?;
+                    cli.session = Some(arg);
+                }
+                // Hidden from the usage, since it must never be used on a real system
+                #[cfg(feature = "insecure-test-credentials")]
+                (_, "--insecure-test-credentials") => {
+                    let (_, arg) = args
+                        .next()
+                        .ok_or(CliError::MissingArgument("insecure-test-credentials"))?;
+                    cli.test_credentials = Some(arg);
+                }
                 (_, "--config") | (_, "-c") => {
                     let (_, arg) = args.next().ok_or(CliError::MissingArgument("config"))?;
                     let arg = PathBuf::from(arg);
@@ -111,3 +169,51 @@ impl Cli {
         Ok(cli)
     }
 }
+
+/// Quote and escape a string to be used within JSON output
+pub fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+
+    json
+}
+
+/// The JSON output for when lemurs failed
+pub fn json_error(reason: &str) -> String {
+    format!(
+        "{{\"status\":\"error\",\"reason\":{}}}",
+        json_string(reason)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_escaping() {
+        assert_eq!(json_string("sway"), r#""sway""#);
+        assert_eq!(json_string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(json_string("a\nb\u{1b}"), r#""a\nb\u001b""#);
+
+        assert_eq!(
+            json_error("failed"),
+            r#"{"status":"error","reason":"failed"}"#
+        );
+    }
+}
@@ -1,10 +1,15 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufReader, Read};
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::process;
+use std::str::FromStr;
 
 use crossterm::event::KeyCode;
 use log::error;
+use serde::de::{self, Deserializer, Visitor};
 use serde::Deserialize;
 
 use tui::style::{Color, Modifier};
@@ -151,18 +156,84 @@ macro_rules! toml_config_struct {
 }
 
 toml_config_struct! { Config, PartialConfig,
-    tty => u8,
+    tty => Tty,
+    session_tty => u8,
 
     pam_service => String,
+    fast_relogin_minutes => u64,
+    fast_relogin_pam_service => String,
 
     shell_login_flag => ShellLoginFlag,
-
+    use_login_shell => bool,
+    session_working_directory => String,
+    session_wrapper => Vec<String>,
+    environment_file => String,
+    provide_lock_command => bool,
+    show_starting_screen => bool,
+    clear_on_session_switch => ScreenClearing,
+
+    session_output => SessionOutput,
+    session_log_path => String,
+    session_terminate_timeout => u64,
+    x_start_delay => u64,
+    console_keymap_to_x => bool,
+    session_pid_file => String,
+
+    allow_multiple_sessions => bool,
+
+    allow_console_switch => bool,
+    console_switch_key => String,
+    console_switch_tty => u8,
+
+    session_details_key => String,
+
+    login_trigger_pipe => String,
+    sighup_action => HangupAction,
+
+    status_socket => String,
+    status_socket_users => Vec<String>,
+
+    on_login_cmd => String,
+    on_login_strict => bool,
+    on_login_timeout => u64,
+    on_logout_cmd => String,
+    on_logout_timeout => u64,
+    auth_state_cmd => String,
+
+    terminal_mode => TerminalMode,
+    cursor_shape => CursorShape,
+    cursor_blink => bool,
+
+    show_version => bool,
+    version_color => String,
+    show_breadcrumb => bool,
+    breadcrumb_color => String,
     focus_behaviour => FocusBehaviour,
+    field_order => Vec<LoginField>,
+    reset_after_attempts => u32,
 
+    layout => LayoutConfig [PartialLayoutConfig],
     power_controls => PowerControlConfig [PartialPowerControlConfig],
     environment_switcher => SwitcherConfig [PartialSwitcherConfig],
     username_field => UsernameFieldConfig [PartialUsernameFieldConfig],
     password_field => PasswordFieldConfig [PartialPasswordFieldConfig],
+    motd => MotdConfig [PartialMotdConfig],
+    greeting => GreetingConfig [PartialGreetingConfig],
+    status_message => StatusMessageConfig [PartialStatusMessageConfig],
+    failure_fallback => FailureFallbackConfig [PartialFailureFallbackConfig],
+    maintenance => MaintenanceConfig [PartialMaintenanceConfig],
+    wayland => WaylandConfig [PartialWaylandConfig],
+    on_screen_keyboard => OnScreenKeyboardConfig [PartialOnScreenKeyboardConfig],
+}
+
+toml_config_struct! { LayoutConfig, PartialLayoutConfig,
+    horizontal_margin => u16,
+    vertical_margin => u16,
+
+    power_menu_margin => u16,
+    environment_switcher_margin => u16,
+    username_field_margin => u16,
+    password_field_margin => u16,
 }
 
 toml_config_struct! { PowerControlConfig, PartialPowerControlConfig,
@@ -180,13 +251,24 @@ toml_config_struct! { PowerControlConfig, PartialPowerControlConfig,
     reboot_key => String,
     reboot_cmd => String,
 
+    idle_timeout => u64,
+    idle_cmd => String,
+
     hint_margin => u16,
 }
 
 toml_config_struct! { SwitcherConfig, PartialSwitcherConfig,
     include_tty_shell => bool,
+    disabled_sessions => Vec<String>,
+    hide_single => bool,
 
     remember => bool,
+    remember_per_user => bool,
+    missing_environment => MissingEnvironment,
+    default_environment => String,
+    host_default_environments => HashMap<String, String>,
+
+    enter_behaviour => SwitcherEnterBehaviour,
 
     show_movers => bool,
     mover_color => String,
@@ -247,14 +329,155 @@ toml_config_struct! { InputFieldStyle, PartialInputFieldStyle,
 
 toml_config_struct! { UsernameFieldConfig, PartialUsernameFieldConfig,
     remember => bool,
+
+    use_fixed_username => bool,
+    fixed_username => String,
+
+    advance_characters => String,
+    advance_length => u16,
+
+    focus_on_typing => bool,
+
     style => InputFieldStyle [PartialInputFieldStyle],
 }
 
 toml_config_struct! { PasswordFieldConfig, PartialPasswordFieldConfig,
     content_replacement_character => char,
+    hide_length => bool,
+    fixed_length => u16,
+    clear_on_failure => bool,
     style => InputFieldStyle [PartialInputFieldStyle],
 }
 
+toml_config_struct! { OnScreenKeyboardConfig, PartialOnScreenKeyboardConfig,
+    enabled => bool,
+
+    color => String,
+    modifiers => String,
+}
+
+toml_config_struct! { MotdConfig, PartialMotdConfig,
+    path => String,
+    command => String,
+    command_interval => u64,
+
+    color => String,
+    modifiers => String,
+}
+
+toml_config_struct! { StatusMessageConfig, PartialStatusMessageConfig,
+    info_color => String,
+    success_color => String,
+    error_color => String,
+}
+
+toml_config_struct! { GreetingConfig, PartialGreetingConfig,
+    show_greeting => bool,
+
+    morning => String,
+    afternoon => String,
+    evening => String,
+    night => String,
+
+    color => String,
+    modifiers => String,
+}
+
+toml_config_struct! { MaintenanceConfig, PartialMaintenanceConfig,
+    enabled => bool,
+    file => String,
+    message => String,
+    admins => Vec<String>,
+}
+
+toml_config_struct! { WaylandConfig, PartialWaylandConfig,
+    wrapper => Vec<String>,
+    args => Vec<String>,
+    session_args => HashMap<String, Vec<String>>,
+}
+
+toml_config_struct! { FailureFallbackConfig, PartialFailureFallbackConfig,
+    max_failures => u32,
+    action => FailureAction,
+
+    instructions => String,
+    rescue_cmd => String,
+
+    max_session_failures => u32,
+    safe_session => String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum FailureAction {
+    #[serde(rename = "instructions")]
+    Instructions,
+    #[serde(rename = "rescue-shell")]
+    RescueShell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum CursorShape {
+    #[serde(rename = "default")]
+    Default,
+    #[serde(rename = "block")]
+    Block,
+    #[serde(rename = "underline")]
+    Underline,
+    #[serde(rename = "bar")]
+    Bar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum TerminalMode {
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "full")]
+    Full,
+    #[serde(rename = "basic")]
+    Basic,
+}
+
+/// Terminals that cannot be relied on to show colors, borders or an alternate screen. Serial
+/// consoles are usually one of these.
+const BASIC_TERMINALS: &[&str] = &["dumb", "vt52", "vt100", "vt102", "vt220", "vt320", "vt420"];
+
+impl TerminalMode {
+    /// Whether the basic rendering should be used, which works on serial consoles
+    pub fn is_basic(self) -> bool {
+        match self {
+            Self::Auto => {
+                matches!(std::env::var("TERM"), Ok(term) if is_basic_terminal(&term))
+            }
+            Self::Full => false,
+            Self::Basic => true,
+        }
+    }
+}
+
+fn is_basic_terminal(term: &str) -> bool {
+    BASIC_TERMINALS.contains(&term)
+}
+
+/// What pressing Enter does in the environment switcher
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SwitcherEnterBehaviour {
+    #[serde(rename = "advance")]
+    Advance,
+    #[serde(rename = "stay")]
+    Stay,
+}
+
+/// What to select when the remembered environment is no longer installed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum MissingEnvironment {
+    #[serde(rename = "first")]
+    First,
+    #[serde(rename = "default")]
+    Default,
+    #[serde(rename = "notice")]
+    Notice,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub enum FocusBehaviour {
     #[serde(rename = "default")]
@@ -269,6 +492,16 @@ pub enum FocusBehaviour {
     Password,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum LoginField {
+    #[serde(rename = "environment")]
+    Environment,
+    #[serde(rename = "username")]
+    Username,
+    #[serde(rename = "password")]
+    Password,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub enum ShellLoginFlag {
     #[serde(rename = "none")]
@@ -279,6 +512,160 @@ pub enum ShellLoginFlag {
     Long,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum HangupAction {
+    #[serde(rename = "reload")]
+    Reload,
+    #[serde(rename = "reexec")]
+    Reexec,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ScreenClearing {
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "screen")]
+    Screen,
+    #[serde(rename = "scrollback")]
+    Scrollback,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum SessionOutput {
+    #[serde(rename = "log")]
+    Log,
+    #[serde(rename = "file")]
+    File,
+    #[serde(rename = "none")]
+    None,
+}
+
+/// The virtual terminals that can be used. The kernel supports at most 63 of them.
+pub const TTY_RANGE: RangeInclusive<u8> = 1..=63;
+
+/// The tty which contains lemurs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tty {
+    /// The tty that lemurs was started on
+    Auto,
+    Number(u8),
+}
+
+impl Tty {
+    /// The number of the tty, or `None` when it has not been determined yet
+    pub fn number(self) -> Option<u8> {
+        match self {
+            Self::Auto => None,
+            Self::Number(number) => Some(number),
+        }
+    }
+
+    /// Whether the tty can actually be used as a virtual terminal
+    pub fn is_valid(self) -> bool {
+        match self {
+            Self::Auto => true,
+            Self::Number(number) => TTY_RANGE.contains(&number),
+        }
+    }
+}
+
+impl fmt::Display for Tty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Number(number) => write!(f, "{number}"),
+        }
+    }
+}
+
+impl FromStr for Tty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "auto" => Ok(Self::Auto),
+            number => number
+                .parse()
+                .map(Self::Number)
+                .map_err(|_| format!("'{s}' is not a tty number or \"auto\"")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Tty {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TtyVisitor;
+
+        impl<'de> Visitor<'de> for TtyVisitor {
+            type Value = Tty;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a tty number or \"auto\"")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Tty, E> {
+                u8::try_from(value)
+                    .map(Tty::Number)
+                    .map_err(|_| E::custom(format!("tty {value} is out of range")))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Tty, E> {
+                u8::try_from(value)
+                    .map(Tty::Number)
+                    .map_err(|_| E::custom(format!("tty {value} is out of range")))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Tty, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(TtyVisitor)
+    }
+}
+
+impl Config {
+    /// The tty that sessions run on. This is the tty of lemurs, unless a separate `session_tty` is
+    /// configured.
+    pub fn session_vt(&self) -> Option<u8> {
+        if self.session_tty == 0 {
+            self.tty.number()
+        } else {
+            Some(self.session_tty)
+        }
+    }
+
+    /// Check the values that can be parsed but are not usable
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid_tty = |option: &str, tty: Tty| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Invalid {option} '{tty}'. Only ttys {} to {} are allowed",
+                    TTY_RANGE.start(),
+                    TTY_RANGE.end()
+                ),
+            )
+        };
+
+        if !self.tty.is_valid() {
+            return Err(invalid_tty("tty", self.tty));
+        }
+
+        let console_switch_tty = Tty::Number(self.console_switch_tty);
+        if !console_switch_tty.is_valid() {
+            return Err(invalid_tty("console_switch_tty", console_switch_tty));
+        }
+
+        let session_tty = Tty::Number(self.session_tty);
+        if self.session_tty != 0 && !session_tty.is_valid() {
+            return Err(invalid_tty("session_tty", session_tty));
+        }
+
+        Ok(())
+    }
+}
+
 impl Default for Config {
     fn default() -> Config {
         toml::from_str(include_str!("../extra/config.toml")).unwrap_or_else(|_| {
@@ -288,12 +675,82 @@ impl Default for Config {
     }
 }
 
+impl Default for LayoutConfig {
+    fn default() -> LayoutConfig {
+        Config::default().layout
+    }
+}
+
 impl PartialConfig {
     pub fn from_file(path: &Path) -> io::Result<PartialConfig> {
         let file = File::open(path)?;
         let mut buf_reader = BufReader::new(file);
         let mut contents = String::new();
         buf_reader.read_to_string(&mut contents)?;
-        Ok(toml::from_str(&contents).expect("Given configuration file contains errors."))
+        toml::from_str(&contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_tty(value: &str) -> Result<Tty, toml::de::Error> {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            tty: Tty,
+        }
+
+        toml::from_str::<Wrapper>(&format!("tty = {value}")).map(|wrapper| wrapper.tty)
+    }
+
+    #[test]
+    fn tty_values() {
+        assert_eq!(parse_tty("2").unwrap(), Tty::Number(2));
+        assert_eq!(parse_tty("\"auto\"").unwrap(), Tty::Auto);
+        assert!(parse_tty("300").is_err());
+        assert!(parse_tty("-1").is_err());
+        assert!(parse_tty("\"first\"").is_err());
+    }
+
+    #[test]
+    fn basic_terminals() {
+        assert!(is_basic_terminal("vt220"));
+        assert!(is_basic_terminal("dumb"));
+        assert!(!is_basic_terminal("linux"));
+        assert!(!is_basic_terminal("xterm-256color"));
+    }
+
+    #[test]
+    fn validate_tty_range() {
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+
+        for (tty, is_valid) in [
+            (Tty::Auto, true),
+            (Tty::Number(1), true),
+            (Tty::Number(63), true),
+            (Tty::Number(0), false),
+            (Tty::Number(64), false),
+        ] {
+            config.tty = tty;
+            assert_eq!(config.validate().is_ok(), is_valid, "{tty}");
+        }
+    }
+
+    #[test]
+    fn session_vt() {
+        let mut config = Config {
+            tty: Tty::Number(2),
+            ..Config::default()
+        };
+        assert_eq!(config.session_vt(), Some(2));
+
+        config.session_tty = 7;
+        assert_eq!(config.session_vt(), Some(7));
+        assert!(config.validate().is_ok());
+
+        config.session_tty = 64;
+        assert!(config.validate().is_err());
     }
 }
@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::Path;
+
+/// The process names of display managers that commonly conflict with lemurs
+const KNOWN_DISPLAY_MANAGERS: &[&str] = &[
+    "gdm", "gdm3", "sddm", "lightdm", "lxdm", "xdm", "ly", "greetd", "slim", "nodm", "entrance",
+];
+
+/// Find other display managers that are currently running
+///
+/// These often hold on to the GPU or the VT, which makes lemurs show a black screen. This is only
+/// used to warn the user.
+pub fn running_display_managers() -> Vec<String> {
+    running_display_managers_in(Path::new("/proc"))
+}
+
+fn running_display_managers_in(proc_path: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(proc_path) else {
+        return Vec::new();
+    };
+
+    let mut found = Vec::new();
+    for entry in entries.flatten() {
+        // Only the numeric directories are processes
+        let file_name = entry.file_name();
+        let is_process = file_name
+            .to_string_lossy()
+            .bytes()
+            .all(|b| b.is_ascii_digit());
+        if !is_process {
+            continue;
+        }
+
+        let Ok(comm) = fs::read_to_string(entry.path().join("comm")) else {
+            continue;
+        };
+        let comm = comm.trim();
+
+        if KNOWN_DISPLAY_MANAGERS.contains(&comm) && !found.iter().any(|dm| dm == comm) {
+            found.push(comm.to_string());
+        }
+    }
+
+    found.sort();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_known_display_managers() {
+        let proc_path =
+            std::env::temp_dir().join(format!("lemurs-dm-check-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&proc_path);
+
+        for (pid, comm) in [
+            ("1", "systemd"),
+            ("42", "sddm"),
+            ("43", "sddm"),
+            ("7", "gdm"),
+        ] {
+            fs::create_dir_all(proc_path.join(pid)).unwrap();
+            fs::write(proc_path.join(pid).join("comm"), format!("{comm}\n")).unwrap();
+        }
+        fs::create_dir_all(proc_path.join("self")).unwrap();
+        fs::write(proc_path.join("self").join("comm"), "lightdm\n").unwrap();
+
+        assert_eq!(running_display_managers_in(&proc_path), ["gdm", "sddm"]);
+
+        fs::remove_dir_all(&proc_path).unwrap();
+    }
+}
@@ -9,7 +9,7 @@ use log::{error, info};
 pub struct EnvironmentContainer {
     snapshot: HashMap<String, String>,
     snapshot_pwd: String,
-    owned: HashMap<&'static str, String>,
+    owned: HashMap<String, String>,
 }
 
 impl EnvironmentContainer {
@@ -25,31 +25,40 @@ impl EnvironmentContainer {
     }
 
     /// Set an environment variable and own the value
-    pub fn set(&mut self, key: &'static str, value: impl Into<String>) {
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
         let value = value.into();
 
         env::set_var(key, &value);
         info!("Set environment variable '{}' to '{}'", key, value);
 
-        self.owned.insert(key, value);
+        self.owned.insert(key.to_string(), value);
     }
 
     /// Set an environment variable if it is not already set
     ///
     /// If the variable was already set, then the [`EnvironmentContainer`] considers the value as
     /// one of its own.
-    pub fn set_or_own(&mut self, key: &'static str, value: impl Into<String>) {
+    pub fn set_or_own(&mut self, key: &str, value: impl Into<String>) {
         if let Ok(value) = env::var(key) {
             info!(
                 "Skipped setting environment variable '{}'. It was already set to '{}'",
                 key, value
             );
-            self.owned.insert(key, value);
+            self.owned.insert(key.to_string(), value);
         } else {
             self.set(key, value)
         }
     }
 
+    /// Whether a variable was set or changed by something else than the container since the
+    /// snapshot, e.g. by a PAM module
+    pub fn is_foreign(&self, key: &str) -> bool {
+        match env::var(key) {
+            Ok(value) => !self.owned.contains_key(key) && self.snapshot.get(key) != Some(&value),
+            Err(_) => false,
+        }
+    }
+
     /// Sets the working directory
     pub fn set_current_dir(&mut self, value: impl Into<String>) {
         let value = value.into();
@@ -72,6 +81,21 @@ impl Drop for EnvironmentContainer {
             }
         }
 
+        // Remove the variables that were added during the session by something else than the
+        // container. PAM modules set variables this way (e.g. `KRB5CCNAME` by `pam_krb5`), and
+        // these should not leak into the session of the next user.
+        let added: Vec<String> = env::vars()
+            .map(|(key, _)| key)
+            .filter(|key| !self.snapshot.contains_key(key))
+            .collect();
+        for key in added {
+            info!(
+                "Removing environment variable '{}' set during the session",
+                key
+            );
+            env::remove_var(key);
+        }
+
         // Restore all snapshot values for which disappeared
         info!("Reverting to environment before session");
         for (key, value) in self.snapshot.iter() {
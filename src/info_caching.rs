@@ -1,4 +1,5 @@
 use log::{info, warn};
+use std::collections::HashMap;
 use std::fs::{read_to_string, write};
 
 pub const CACHE_PATH: &str = "/var/cache/lemurs";
@@ -7,12 +8,17 @@ const USERNAME_LENGTH_LIMIT: usize = 32;
 // Saved in the /var/cache/lemurs file as
 // ```
 // ENVIRONMENT\n
-// USERNAME
+// USERNAME\n
+// USERNAME ENVIRONMENT\n
+// ...
 // ```
-#[derive(Debug, Clone)]
+//
+// The lines after the second line contain the last environment per username.
+#[derive(Debug, Clone, Default)]
 pub struct CachedInfo {
     environment: Option<String>,
     username: Option<String>,
+    user_environments: HashMap<String, String>,
 }
 
 fn verify_username(username: &str) -> bool {
@@ -46,6 +52,214 @@ impl CachedInfo {
     pub fn username(&self) -> Option<&str> {
         self.username.as_deref()
     }
+
+    /// The last environment used by a specific user
+    pub fn user_environment(&self, username: &str) -> Option<&str> {
+        self.user_environments.get(username).map(String::as_str)
+    }
+
+    /// All the users with their last environment
+    pub fn user_environments(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.user_environments
+            .iter()
+            .map(|(username, environment)| (username.as_str(), environment.as_str()))
+    }
+}
+
+fn parse_user_environments<'a>(lines: impl Iterator<Item = &'a str>) -> HashMap<String, String> {
+    let mut user_environments = HashMap::new();
+
+    for line in lines {
+        let Some((username, environment)) = line.split_once(' ') else {
+            warn!("Skipping invalid user environment line in cache file");
+            continue;
+        };
+
+        if !verify_username(username) {
+            warn!("Cached username is not a valid username and its environment is therefore not loaded.");
+            continue;
+        }
+
+        user_environments.insert(username.to_string(), environment.to_string());
+    }
+
+    user_environments
+}
+
+impl CachedInfo {
+    /// Read the cache from the content of the cache file
+    fn parse(content: &str) -> Self {
+        // Remove any line feeds
+        let cached = content.trim();
+
+        let mut lines = cached.lines();
+
+        let cached_environment = lines.next();
+        let cached_username = lines.next();
+
+        info!(
+            "Read cache file and found environment '{}' and username '{}'",
+            cached_environment.unwrap_or("None"),
+            cached_username.unwrap_or("None")
+        );
+
+        let cached_username = if let Some(cached_username) = cached_username {
+            // Username length check
+            if cached_username.len() > USERNAME_LENGTH_LIMIT {
+                warn!("Cached username is too long and is therefore not loaded.");
+                None
+
+            // Username validity check (through regex)
+            } else if !verify_username(cached_username) {
+                warn!("Cached username is not a valid username and is therefore not loaded.");
+                None
+            } else {
+                Some(cached_username)
+            }
+        } else {
+            cached_username
+        };
+
+        CachedInfo {
+            environment: cached_environment.map(|x| x.to_string()),
+            username: cached_username.map(|x| x.to_string()),
+            user_environments: parse_user_environments(lines),
+        }
+    }
+
+    /// The content of the cache file
+    fn to_file_content(&self) -> String {
+        let mut content = format!(
+            "{}\n{}\n",
+            self.environment.as_deref().unwrap_or_default(),
+            self.username.as_deref().unwrap_or_default()
+        );
+
+        for (username, environment) in self.user_environments.iter() {
+            content.push_str(&format!("{username} {environment}\n"));
+        }
+
+        content
+    }
+
+    /// Remember newly selected values. See [`InfoCache::set`].
+    ///
+    /// Returns whether anything should be stored.
+    fn update(
+        &mut self,
+        environment: Option<&str>,
+        username: Option<&str>,
+        user_environment: Option<(&str, &str)>,
+    ) -> bool {
+        let username = if let Some(username) = username {
+            // Username length check
+            if username.len() > USERNAME_LENGTH_LIMIT {
+                warn!("Username is too long and is therefore not cached.");
+                return false;
+            }
+
+            // Username validity check (through regex)
+            if !verify_username(username) {
+                warn!("Username is not a valid username and is therefore not cached.");
+                None
+            } else {
+                Some(username)
+            }
+        } else {
+            None
+        };
+
+        if let Some((username, environment)) = user_environment {
+            if verify_username(username) {
+                self.user_environments
+                    .insert(username.to_string(), environment.to_string());
+            } else {
+                warn!(
+                    "Username is not a valid username and its environment is therefore not cached."
+                );
+            }
+        }
+
+        self.environment = environment.map(str::to_string);
+        self.username = username.map(str::to_string);
+
+        true
+    }
+}
+
+/// Where the username and environments are remembered in between logins
+pub trait InfoCache: Send + Sync {
+    /// The information that is currently cached
+    fn get(&self) -> CachedInfo;
+
+    /// Set the cached information
+    ///
+    /// If `user_environment` is given, the environment for that user is stored alongside the
+    /// environments of the other users that were already cached.
+    fn set(
+        &self,
+        environment: Option<&str>,
+        username: Option<&str>,
+        user_environment: Option<(&str, &str)>,
+    );
+}
+
+/// The cache in the [`CACHE_PATH`] file, which is used outside of tests
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileCache;
+
+impl InfoCache for FileCache {
+    fn get(&self) -> CachedInfo {
+        get_cached_information()
+    }
+
+    fn set(
+        &self,
+        environment: Option<&str>,
+        username: Option<&str>,
+        user_environment: Option<(&str, &str)>,
+    ) {
+        set_cache(environment, username, user_environment);
+    }
+}
+
+/// A cache that only lives in memory, so tests do not touch the cache of the system
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    info: std::sync::Mutex<CachedInfo>,
+}
+
+#[cfg(test)]
+impl MemoryCache {
+    pub fn new(environment: Option<&str>, username: Option<&str>) -> Self {
+        Self {
+            info: std::sync::Mutex::new(CachedInfo {
+                environment: environment.map(str::to_string),
+                username: username.map(str::to_string),
+                user_environments: HashMap::new(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+impl InfoCache for MemoryCache {
+    fn get(&self) -> CachedInfo {
+        self.info.lock().unwrap().clone()
+    }
+
+    fn set(
+        &self,
+        environment: Option<&str>,
+        username: Option<&str>,
+        user_environment: Option<(&str, &str)>,
+    ) {
+        self.info
+            .lock()
+            .unwrap()
+            .update(environment, username, user_environment);
+    }
 }
 
 pub fn get_cached_information() -> CachedInfo {
@@ -55,81 +269,28 @@ pub fn get_cached_information() -> CachedInfo {
     );
 
     match read_to_string(CACHE_PATH) {
-        Ok(cached) => {
-            // Remove any line feeds
-            let cached = cached.trim().to_string();
-
-            let mut lines = cached.lines();
-
-            let cached_environment = lines.next();
-            let cached_username = lines.next();
-
-            info!(
-                "Read cache file and found environment '{}' and username '{}'",
-                cached_environment.unwrap_or("None"),
-                cached_username.unwrap_or("None")
-            );
-
-            let cached_username = if let Some(cached_username) = cached_username {
-                // Username length check
-                if cached_username.len() > USERNAME_LENGTH_LIMIT {
-                    warn!("Cached username is too long and is therefore not loaded.");
-                    None
-
-                // Username validity check (through regex)
-                } else if !verify_username(cached_username) {
-                    warn!("Cached username is not a valid username and is therefore not loaded.");
-                    None
-                } else {
-                    Some(cached_username)
-                }
-            } else {
-                cached_username
-            };
-
-            CachedInfo {
-                environment: cached_environment.map(|x| x.to_string()),
-                username: cached_username.map(|x| x.to_string()),
-            }
-        }
+        Ok(cached) => CachedInfo::parse(&cached),
         Err(err) => {
             warn!("Unable to read cache file. Reason: '{}'", err);
-            CachedInfo {
-                environment: None,
-                username: None,
-            }
+            CachedInfo::default()
         }
     }
 }
 
-pub fn set_cache(environment: Option<&str>, username: Option<&str>) {
+/// Set the cached information in the cache file. See [`InfoCache::set`].
+pub fn set_cache(
+    environment: Option<&str>,
+    username: Option<&str>,
+    user_environment: Option<(&str, &str)>,
+) {
     info!("Attempting to set cache");
 
-    let username = if let Some(username) = username {
-        // Username length check
-        if username.len() > USERNAME_LENGTH_LIMIT {
-            warn!("Username is too long and is therefore not cached.");
-            return;
-        }
-
-        // Username validity check (through regex)
-        if !verify_username(username) {
-            warn!("Username is not a valid username and is therefore not cached.");
-            None
-        } else {
-            Some(username)
-        }
-    } else {
-        None
-    };
-
-    let cache_file_content = format!(
-        "{}\n{}\n",
-        environment.unwrap_or_default(),
-        username.unwrap_or_default()
-    );
+    let mut cached = get_cached_information();
+    if !cached.update(environment, username, user_environment) {
+        return;
+    }
 
-    match write(CACHE_PATH, cache_file_content) {
+    match write(CACHE_PATH, cached.to_file_content()) {
         Err(err) => {
             warn!("Failed to set username to cache file. Reason: '{}'", err);
         }
@@ -138,3 +299,38 @@ pub fn set_cache(environment: Option<&str>, username: Option<&str>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_file_content() {
+        let mut cached = CachedInfo::default();
+        assert!(cached.update(Some("sway"), Some("lemur"), Some(("lemur", "sway"))));
+
+        let parsed = CachedInfo::parse(&cached.to_file_content());
+        assert_eq!(parsed.environment(), Some("sway"));
+        assert_eq!(parsed.username(), Some("lemur"));
+        assert_eq!(parsed.user_environment("lemur"), Some("sway"));
+    }
+
+    #[test]
+    fn invalid_usernames_are_not_cached() {
+        let cache = MemoryCache::new(Some("bspwm"), Some("lemur"));
+
+        cache.set(Some("sway"), Some("not valid"), Some(("not valid", "sway")));
+        let cached = cache.get();
+        assert_eq!(cached.environment(), Some("sway"));
+        assert_eq!(cached.username(), None);
+        assert_eq!(cached.user_environments().count(), 0);
+
+        // A username that is too long is not cached at all
+        cache.set(
+            Some("bspwm"),
+            Some(&"a".repeat(USERNAME_LENGTH_LIMIT + 1)),
+            None,
+        );
+        assert_eq!(cache.get().environment(), Some("sway"));
+    }
+}
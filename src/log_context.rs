@@ -0,0 +1,62 @@
+use std::sync::Mutex;
+
+use rand::Rng;
+
+/// The context of the login attempt that is in progress, which is added to every log line
+static CONTEXT: Mutex<Option<String>> = Mutex::new(None);
+
+/// The context of a login attempt in the log. The context is removed from the log when this is
+/// dropped.
+pub struct LoginContext {
+    session_id: String,
+}
+
+impl LoginContext {
+    /// Start adding a newly generated session id and the username to the log lines
+    pub fn start(username: &str) -> Self {
+        let session_id = format!("{:08x}", rand::thread_rng().gen::<u32>());
+        set_context(Some(format_context(&session_id, username)));
+
+        Self { session_id }
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+}
+
+impl Drop for LoginContext {
+    fn drop(&mut self) {
+        set_context(None);
+    }
+}
+
+fn format_context(session_id: &str, username: &str) -> String {
+    format!("session={session_id} user={username}")
+}
+
+fn set_context(context: Option<String>) {
+    // A poisoned lock only means that logging panicked somewhere, so the context is still usable
+    *CONTEXT.lock().unwrap_or_else(|err| err.into_inner()) = context;
+}
+
+/// The context that should be added to a log line, if a login attempt is in progress
+pub fn current() -> Option<String> {
+    CONTEXT
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_format() {
+        assert_eq!(
+            format_context("0000abcd", "lemur"),
+            "session=0000abcd user=lemur"
+        );
+    }
+}
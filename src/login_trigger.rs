@@ -0,0 +1,237 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use log::{info, warn};
+use nix::sys::stat::Mode;
+use nix::unistd::mkfifo;
+
+/// A login that is started from outside of the login form
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginTrigger {
+    pub username: String,
+    /// The password to login with. Without it, the password field is left empty.
+    pub password: Option<String>,
+    /// The environment to start instead of the selected one
+    pub session: Option<String>,
+}
+
+/// A line of the login trigger pipe, which only contains a username
+fn parse_username(line: &str) -> Option<LoginTrigger> {
+    let username = line.trim();
+    if username.is_empty() {
+        return None;
+    }
+
+    Some(LoginTrigger {
+        username: username.to_string(),
+        password: None,
+        session: None,
+    })
+}
+
+/// Check that a file with the given type, owner and mode is a pipe that only root can write to
+///
+/// Returns why the pipe cannot be trusted otherwise.
+fn check_pipe(is_fifo: bool, uid: u32, mode: u32) -> Result<(), &'static str> {
+    if !is_fifo {
+        return Err("exists, but is not a named pipe");
+    }
+    if uid != 0 {
+        return Err("is not owned by root");
+    }
+    if mode & 0o022 != 0 {
+        return Err("is writable by other users than root");
+    }
+
+    Ok(())
+}
+
+fn check_pipe_metadata(metadata: &fs::Metadata) -> Result<(), &'static str> {
+    check_pipe(
+        metadata.file_type().is_fifo(),
+        metadata.uid(),
+        metadata.mode(),
+    )
+}
+
+/// Open the pipe at `path` for reading, after checking it again
+///
+/// The pipe may have been replaced since lemurs started, so the opened file is checked itself.
+fn open_pipe(path: &Path) -> io::Result<File> {
+    let pipe = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+
+    match check_pipe_metadata(&pipe.metadata()?) {
+        Ok(()) => Ok(pipe),
+        Err(reason) => Err(io::Error::new(io::ErrorKind::PermissionDenied, reason)),
+    }
+}
+
+/// Listen for login triggers on the named pipe at `path`
+///
+/// Every line that is written to the pipe contains a username to login as, without typing a
+/// password. This lets e.g. a smartcard daemon start the login when a card is inserted, while a
+/// PAM module does the actual authentication. The pipe is created when it does not exist.
+pub fn listen(path: &str) -> Option<Receiver<LoginTrigger>> {
+    listen_with(path, parse_username)
+}
+
+/// Listen for login triggers on the named pipe at `path`, where `parse` reads the trigger of every
+/// line. Lines without a trigger are skipped.
+///
+/// An existing pipe is only used when it is owned by root and nobody else can write to it.
+pub(crate) fn listen_with(
+    path: &str,
+    parse: fn(&str) -> Option<LoginTrigger>,
+) -> Option<Receiver<LoginTrigger>> {
+    let path = Path::new(path);
+
+    // A symbolic link could point anywhere, so the pipe itself is checked
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => {
+            if let Err(reason) = check_pipe_metadata(&metadata) {
+                warn!(
+                    "The login trigger pipe '{}' {}. Not listening for login triggers",
+                    path.display(),
+                    reason
+                );
+                return None;
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            // Only root should be able to trigger a login
+            if let Err(err) = mkfifo(path, Mode::S_IRUSR | Mode::S_IWUSR) {
+                warn!(
+                    "Failed to create the login trigger pipe '{}'. Reason: {}",
+                    path.display(),
+                    err
+                );
+                return None;
+            }
+        }
+        Err(err) => {
+            warn!(
+                "Failed to check the login trigger pipe '{}'. Reason: {}",
+                path.display(),
+                err
+            );
+            return None;
+        }
+    }
+
+    info!("Listening for login triggers on '{}'", path.display());
+
+    let (sender, receiver) = channel();
+    let path = path.to_path_buf();
+    thread::spawn(move || loop {
+        // Opening blocks until something opens the pipe for writing. After the writer closes the
+        // pipe, it is opened again for the next writer.
+        let pipe = match open_pipe(&path) {
+            Ok(pipe) => pipe,
+            Err(err) => {
+                warn!(
+                    "Failed to open the login trigger pipe '{}'. Reason: {}",
+                    path.display(),
+                    err
+                );
+                return;
+            }
+        };
+
+        for line in BufReader::new(pipe).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    warn!("Failed to read a login trigger. Reason: {}", err);
+                    break;
+                }
+            };
+
+            let Some(trigger) = parse(&line) else {
+                continue;
+            };
+
+            // The login form is gone, so nobody is listening anymore
+            if sender.send(trigger).is_err() {
+                return;
+            }
+        }
+    });
+
+    Some(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn username_lines() {
+        assert_eq!(
+            parse_username("  lemur \n"),
+            Some(LoginTrigger {
+                username: "lemur".to_string(),
+                password: None,
+                session: None,
+            })
+        );
+        assert_eq!(parse_username("   "), None);
+    }
+
+    #[test]
+    fn pipe_checks() {
+        assert_eq!(check_pipe(true, 0, 0o600), Ok(()));
+        assert_eq!(check_pipe(true, 0, 0o644), Ok(()));
+    }
+
+    #[test]
+    fn pipe_not_fifo() {
+        assert_eq!(
+            check_pipe(false, 0, 0o600),
+            Err("exists, but is not a named pipe")
+        );
+    }
+
+    #[test]
+    fn pipe_not_owned_by_root() {
+        assert_eq!(check_pipe(true, 1000, 0o600), Err("is not owned by root"));
+    }
+
+    #[test]
+    fn pipe_writable_by_others() {
+        let reason = Err("is writable by other users than root");
+        assert_eq!(check_pipe(true, 0, 0o620), reason);
+        assert_eq!(check_pipe(true, 0, 0o602), reason);
+    }
+
+    #[test]
+    fn untrusted_pipes() {
+        let folder =
+            std::env::temp_dir().join(format!("lemurs-login-trigger-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+
+        let writable = folder.join("writable");
+        mkfifo(&writable, Mode::S_IRUSR | Mode::S_IWUSR).unwrap();
+        fs::set_permissions(&writable, fs::Permissions::from_mode(0o622)).unwrap();
+        assert!(listen(writable.to_str().unwrap()).is_none());
+
+        let trusted = folder.join("trusted");
+        mkfifo(&trusted, Mode::S_IRUSR | Mode::S_IWUSR).unwrap();
+        let link = folder.join("link");
+        std::os::unix::fs::symlink(&trusted, &link).unwrap();
+        assert!(listen(link.to_str().unwrap()).is_none());
+
+        let file = folder.join("file");
+        fs::write(&file, "").unwrap();
+        assert!(listen(file.to_str().unwrap()).is_none());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -1,14 +1,20 @@
 use std::error::Error;
-use std::fs::File;
-use std::io;
-use std::path::Path;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use log::{error, info, warn};
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
 use tui::backend::CrosstermBackend;
 use tui::Terminal;
 
@@ -16,14 +22,21 @@ mod auth;
 mod chvt;
 mod cli;
 mod config;
+mod dm_check;
 mod env_container;
 mod info_caching;
+mod log_context;
+mod login_trigger;
 mod post_login;
+mod signals;
+mod status_socket;
+#[cfg(feature = "insecure-test-credentials")]
+mod test_credentials;
 mod ui;
 
 use auth::try_auth;
-use config::Config;
-use post_login::{EnvironmentStartError, PostLoginEnvironment};
+use config::{Config, MaintenanceConfig, Tty};
+use post_login::{EnvironmentStartError, PostLoginEnvironment, SessionPidFile};
 
 use crate::{
     auth::utmpx::add_utmpx_entry,
@@ -34,16 +47,49 @@ use self::{
     auth::AuthenticationError,
     env_container::EnvironmentContainer,
     post_login::env_variables::{
-        set_basic_variables, set_display, set_seat_vars, set_session_params, set_session_vars,
-        set_xdg_common_paths,
+        set_basic_variables, set_display, set_file_variables, set_lock_command, set_seat_vars,
+        set_session_params, set_session_vars, set_xdg_common_paths,
     },
 };
 
 const DEFAULT_CONFIG_PATH: &str = "/etc/lemurs/config.toml";
+/// The environment variable that can contain the configuration path when `--config` is not given
+const CONFIG_PATH_ENV_VAR: &str = "LEMURS_CONFIG";
 const PREVIEW_LOG_PATH: &str = "lemurs.log";
 const DEFAULT_LOG_PATH: &str = "/var/log/lemurs.log";
+/// The environment variable that marks lemurs as restarted by itself, e.g. on SIGHUP
+const RESTARTED_ENV_VAR: &str = "LEMURS_RESTARTED";
+/// When this file exists, only root is allowed to login. Its contents explain why.
+const NOLOGIN_PATH: &str = "/etc/nologin";
+/// How often a hook command is checked for having finished
+const HOOK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long to wait for the output of a failed hook command, after it exited
+const HOOK_OUTPUT_TIMEOUT: Duration = Duration::from_millis(100);
 
-fn merge_in_configuration(config: &mut Config, config_path: Option<&Path>) {
+/// The exit codes of lemurs for the different categories of failures
+#[derive(Clone, Copy)]
+enum ExitCode {
+    /// An unexpected failure (e.g. the log file cannot be opened)
+    Failure = 1,
+    /// The command line arguments are invalid
+    Usage = 2,
+    /// The given configuration file cannot be loaded
+    Config = 3,
+    /// Lemurs lacks the privileges it needs (e.g. it is not ran as root)
+    Permission = 4,
+    /// Lemurs is started within an existing session
+    InSession = 5,
+    /// The terminal does not support the UI (e.g. raw mode)
+    Terminal = 6,
+}
+
+impl ExitCode {
+    fn exit(self) -> ! {
+        process::exit(self as i32)
+    }
+}
+
+fn merge_in_configuration(config: &mut Config, config_path: Option<&Path>, json: bool) {
     let load_config_path = config_path.unwrap_or_else(|| Path::new(DEFAULT_CONFIG_PATH));
 
     match config::PartialConfig::from_file(load_config_path) {
@@ -52,18 +98,37 @@ fn merge_in_configuration(config: &mut Config, config_path: Option<&Path>) {
                 "Successfully loaded configuration file from '{}'",
                 load_config_path.display()
             );
-            config.merge_in_partial(partial_config)
+            config.merge_in_partial(partial_config);
+
+            if let Err(err) = config.validate() {
+                let message = format!(
+                    "The config file '{}' contains an invalid value.\nReason: {}",
+                    load_config_path.display(),
+                    err
+                );
+                if json {
+                    println!("{}", cli::json_error(&message));
+                } else {
+                    eprintln!("{message}");
+                }
+                ExitCode::Config.exit();
+            }
         }
         Err(err) => {
             // If we have given it a specific config path, it should crash if this file cannot be
             // loaded. If it is the default config location just put a warning in the logs.
             if let Some(config_path) = config_path {
-                eprintln!(
+                let message = format!(
                     "The config file '{}' cannot be loaded.\nReason: {}",
                     config_path.display(),
                     err
                 );
-                process::exit(1);
+                if json {
+                    println!("{}", cli::json_error(&message));
+                } else {
+                    eprintln!("{message}");
+                }
+                ExitCode::Config.exit();
             } else {
                 warn!(
                     "No configuration file loaded from the expected location ({}). Reason: {}",
@@ -74,47 +139,140 @@ fn merge_in_configuration(config: &mut Config, config_path: Option<&Path>) {
     }
 }
 
-fn setup_logger(is_preview: bool) {
+/// Load the configuration again from the location it was loaded from at startup
+pub fn reload_configuration(config_path: Option<&Path>) -> io::Result<Config> {
+    let load_config_path = config_path.unwrap_or_else(|| Path::new(DEFAULT_CONFIG_PATH));
+
+    let partial_config = config::PartialConfig::from_file(load_config_path)?;
+    info!(
+        "Successfully reloaded configuration file from '{}'",
+        load_config_path.display()
+    );
+
+    let mut config = Config::default();
+    config.merge_in_partial(partial_config);
+    config.validate()?;
+    Ok(config)
+}
+
+/// Log to the log file. A restarted lemurs appends to the log of the lemurs before it, instead of
+/// starting a new log.
+fn setup_logger(is_preview: bool, is_restart: bool) {
     let log_path = if is_preview {
         PREVIEW_LOG_PATH
     } else {
         DEFAULT_LOG_PATH
     };
 
-    let log_file = Box::new(File::create(log_path).unwrap_or_else(|_| {
+    let log_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(is_restart)
+        .truncate(!is_restart)
+        .open(log_path);
+    let log_file = Box::new(log_file.unwrap_or_else(|_| {
         eprintln!("Failed to open log file: '{log_path}'");
-        std::process::exit(1);
+        ExitCode::Failure.exit();
     }));
 
     env_logger::builder()
         .filter_level(log::LevelFilter::Info)
         .target(env_logger::Target::Pipe(log_file))
+        .format(|buf, record| {
+            // Lines logged during a login attempt can be filtered on the session id or username
+            match log_context::current() {
+                Some(context) => writeln!(
+                    buf,
+                    "[{:<5} {} {}] {}",
+                    record.level(),
+                    record.target(),
+                    context,
+                    record.args()
+                ),
+                None => writeln!(
+                    buf,
+                    "[{:<5} {}] {}",
+                    record.level(),
+                    record.target(),
+                    record.args()
+                ),
+            }
+        })
         .init();
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse().unwrap_or_else(|err| {
-        eprintln!("{err}\n");
-        cli::usage();
-        std::process::exit(2);
+        // The arguments could not be parsed, so look for the flag by hand
+        if std::env::args().any(|arg| arg == "--json") {
+            println!("{}", cli::json_error(&err.to_string()));
+        } else {
+            eprintln!("{err}\n");
+            cli::usage();
+        }
+        ExitCode::Usage.exit();
     });
 
     // Load and setup configuration
     let mut config = Config::default();
-    merge_in_configuration(&mut config, cli.config.as_deref());
+    let config_path = cli.config.clone().or_else(|| {
+        std::env::var_os(CONFIG_PATH_ENV_VAR)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    });
+    merge_in_configuration(&mut config, config_path.as_deref(), cli.json);
 
     if let Some(cmd) = cli.command {
         match cmd {
             Commands::Envs => {
-                let envs = post_login::get_envs(config.environment_switcher.include_tty_shell);
+                let switcher_config = &config.environment_switcher;
+                let envs = post_login::get_envs(
+                    switcher_config.include_tty_shell,
+                    &switcher_config.disabled_sessions,
+                );
 
-                for (env_name, _) in envs.into_iter() {
-                    println!("{env_name}");
+                if cli.json {
+                    let envs = envs
+                        .iter()
+                        .map(|(env_name, _)| cli::json_string(env_name))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    println!("{{\"status\":\"ok\",\"environments\":[{envs}]}}");
+                } else {
+                    for (env_name, _) in envs.into_iter() {
+                        println!("{env_name}");
+                    }
                 }
             }
             Commands::Cache => {
                 let cached_info = info_caching::get_cached_information();
 
+                if cli.json {
+                    let optional = |value: Option<&str>| {
+                        value.map_or_else(|| "null".to_string(), cli::json_string)
+                    };
+                    let user_environments = cached_info
+                        .user_environments()
+                        .map(|(username, environment)| {
+                            format!(
+                                "{}:{}",
+                                cli::json_string(username),
+                                cli::json_string(environment)
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join(",");
+
+                    println!(
+                        "{{\"status\":\"ok\",\"environment\":{},\"username\":{},\"user_environments\":{{{}}}}}",
+                        optional(cached_info.environment()),
+                        optional(cached_info.username()),
+                        user_environments
+                    );
+
+                    return Ok(());
+                }
+
                 let environment = cached_info.environment().unwrap_or("No cached value");
                 let username = cached_info.username().unwrap_or("No cached value");
 
@@ -125,6 +283,10 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                 println!("environment: '{environment}'");
                 println!("username: '{username}'");
+
+                for (username, environment) in cached_info.user_environments() {
+                    println!("environment for '{username}': '{environment}'");
+                }
             }
             Commands::Help => {
                 cli::usage();
@@ -132,29 +294,55 @@ fn main() -> Result<(), Box<dyn Error>> {
             Commands::Version => {
                 println!("{}", env!("CARGO_PKG_VERSION"));
             }
+            Commands::TestAuth(username) => test_authentication(&username, &config, cli.json),
+            Commands::Lock => lock_screen(&config, !cli.no_altscreen)?,
         }
 
         return Ok(());
     }
 
+    // The sessions started later should not see this
+    let is_restart = std::env::var_os(RESTARTED_ENV_VAR).is_some();
+    std::env::remove_var(RESTARTED_ENV_VAR);
+
     // Setup the logger
     if !cli.no_log {
-        setup_logger(cli.preview);
+        setup_logger(cli.preview, is_restart);
         info!("Lemurs logger is running");
+        info!(
+            "Lemurs version {} ({} build for {}-{})",
+            env!("CARGO_PKG_VERSION"),
+            if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            },
+            std::env::consts::ARCH,
+            std::env::consts::OS
+        );
     }
 
     if !cli.preview {
         if std::env::var("XDG_SESSION_TYPE").is_ok() {
             eprintln!("Lemurs cannot be ran without `--preview` within an existing session. Namely, `XDG_SESSION_TYPE` is set.");
             error!("Lemurs cannot be started when within an existing session. Namely, `XDG_SESSION_TYPE` is set.");
-            std::process::exit(1);
+            ExitCode::InSession.exit();
         }
 
         let uid = users::get_current_uid();
         if users::get_current_uid() != 0 {
             eprintln!("Lemurs needs to be ran as root. Found user id '{uid}'");
             error!("Lemurs not ran as root. Found user id '{uid}'");
-            std::process::exit(1);
+            ExitCode::Permission.exit();
+        }
+
+        let other_dms = dm_check::running_display_managers();
+        if !other_dms.is_empty() {
+            let other_dms = other_dms.join(", ");
+            eprintln!("WARNING: Another display manager is running ({other_dms}). This may cause lemurs to show a black screen. Disable the other display manager to use lemurs.");
+            warn!(
+                "Another display manager is running ({other_dms}). This may conflict with lemurs."
+            );
         }
 
         if let Some(tty) = cli.tty {
@@ -162,29 +350,126 @@ fn main() -> Result<(), Box<dyn Error>> {
             config.tty = tty;
         }
 
+        let tty = match config.tty {
+            Tty::Number(tty) => tty,
+            Tty::Auto => match chvt::current_vt() {
+                Some(tty) if Tty::Number(tty).is_valid() => {
+                    info!("Automatically selected tty {tty}");
+                    tty
+                }
+                _ => {
+                    eprintln!("Lemurs cannot determine the tty it was started on. Set the tty in the configuration or with the --tty flag.");
+                    error!("The tty is set to 'auto', but lemurs was not started on a tty");
+                    ExitCode::Config.exit();
+                }
+            },
+        };
+        config.tty = Tty::Number(tty);
+
         // Switch to the proper tty
-        info!("Switching to tty {}", config.tty);
+        info!("Switching to tty {tty}");
 
-        unsafe { chvt::chvt(config.tty.into()) }.unwrap_or_else(|err| {
-            error!("Failed to switch tty {}. Reason: {err}", config.tty);
+        unsafe { chvt::chvt(tty.into()) }.unwrap_or_else(|err| {
+            // Without the proper privileges, lemurs will not be able to start any sessions either.
+            if err.is_permission_denied() {
+                eprintln!(
+                    "Lemurs is not permitted to switch to tty {tty}. Lemurs must be ran as root to switch virtual terminals."
+                );
+                error!("Not permitted to switch to tty {tty}. Reason: {err}");
+                ExitCode::Permission.exit();
+            }
+
+            error!("Failed to switch tty {tty}. Reason: {err}");
         });
     }
 
+    // Allow the configuration to be reloaded with SIGUSR1
+    if let Err(err) = signals::install_handlers() {
+        warn!("Failed to install signal handlers. Reason: {err}");
+    }
+
+    if !config.status_socket.is_empty() {
+        status_socket::listen(&config.status_socket, &config.status_socket_users);
+    }
+
     // Start application
-    let mut terminal = tui_enable()?;
-    let login_form = ui::LoginForm::new(config, cli.preview);
-    login_form.run(&mut terminal)?;
-    tui_disable(terminal)?;
+    // The alternate screen is not supported by many serial consoles
+    let basic_terminal = config.terminal_mode.is_basic();
+    if basic_terminal {
+        info!("Using the basic terminal mode");
+    }
+    let use_alternate_screen = !cli.no_altscreen && !basic_terminal;
+
+    // The alternate screen would hide this as soon as the UI starts
+    #[cfg(feature = "insecure-test-credentials")]
+    if let Some(pipe) = &cli.test_credentials {
+        eprintln!("WARNING: Accepting login credentials from the pipe '{pipe}'. This is only meant for automated testing and is insecure on a real system.");
+    }
+
+    let mut terminal = match tui_enable(use_alternate_screen) {
+        Ok(terminal) => terminal,
+        Err(err) => {
+            error!("Failed to initialize the terminal UI. Reason: {err}");
+            report_tui_failure(&err);
+            ExitCode::Terminal.exit();
+        }
+    };
+    let login_form =
+        ui::LoginForm::new(config, cli.preview, config_path).with_initial_session(cli.session);
+    #[cfg(feature = "insecure-test-credentials")]
+    let login_form = login_form.with_test_credentials(cli.test_credentials);
+    let exit = login_form.run(&mut terminal, ui::TerminalEvents, use_alternate_screen)?;
+    tui_disable(terminal, use_alternate_screen)?;
+
+    if exit == ui::FormExit::Reexec {
+        reexec();
+    }
 
     info!("Lemurs is booting down");
 
     Ok(())
 }
 
-pub fn tui_enable() -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
+/// Replace lemurs with its binary, started with the same arguments
+///
+/// When the binary was upgraded while lemurs was running, the new version is started.
+fn reexec() -> ! {
+    let binary = match std::env::current_exe() {
+        Ok(binary) => binary,
+        Err(err) => {
+            error!("Failed to find the lemurs binary to restart. Reason: {err}");
+            ExitCode::Failure.exit();
+        }
+    };
+    // The link to a replaced binary points to the old file, which is marked as deleted
+    let binary = binary
+        .to_str()
+        .and_then(|binary| binary.strip_suffix(" (deleted)"))
+        .map(PathBuf::from)
+        .unwrap_or(binary);
+
+    info!("Restarting lemurs from '{}'", binary.display());
+    let err = process::Command::new(&binary)
+        .args(std::env::args_os().skip(1))
+        .env(RESTARTED_ENV_VAR, "1")
+        .exec();
+
+    error!("Failed to restart lemurs. Reason: {err}");
+    ExitCode::Failure.exit();
+}
+
+/// Prepare the terminal for drawing the TUI
+///
+/// Without the alternate screen, everything printed before and during the TUI stays within the
+/// scrollback of the terminal. This is useful for debugging.
+pub fn tui_enable(
+    use_alternate_screen: bool,
+) -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    if use_alternate_screen {
+        execute!(stdout, EnterAlternateScreen)?;
+    }
     let backend = CrosstermBackend::new(stdout);
     let terminal = Terminal::new(backend)?;
 
@@ -193,9 +478,136 @@ pub fn tui_enable() -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
     Ok(terminal)
 }
 
-pub fn tui_disable(mut terminal: Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
+/// Read a password from the console without echoing it
+fn read_password(prompt: &str) -> io::Result<String> {
+    use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+    use std::io::Write;
+
+    eprint!("{prompt}");
+    io::stderr().flush()?;
+
+    // Input that does not come from a terminal (e.g. a pipe) is never echoed
+    let original = tcgetattr(libc::STDIN_FILENO).ok();
+    if let Some(original) = &original {
+        let mut silent = original.clone();
+        silent.local_flags.remove(LocalFlags::ECHO);
+        tcsetattr(libc::STDIN_FILENO, SetArg::TCSANOW, &silent)?;
+    }
+
+    let mut password = String::new();
+    let result = io::stdin().read_line(&mut password);
+
+    if let Some(original) = &original {
+        tcsetattr(libc::STDIN_FILENO, SetArg::TCSANOW, original)?;
+        eprintln!();
+    }
+    result?;
+
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Authenticate a user the same way as the login form does, without starting a session
+///
+/// This separates problems with the PAM configuration from problems with the sessions.
+fn test_authentication(username: &str, config: &Config, json: bool) {
+    let password = match read_password(&format!("Password for '{username}': ")) {
+        Ok(password) => password,
+        Err(err) => {
+            eprintln!("Failed to read the password. Reason: {err}");
+            ExitCode::Failure.exit();
+        }
+    };
+
+    match try_auth(username, &password, &config.pam_service) {
+        Ok(auth_session) => {
+            if json {
+                println!("{{\"status\":\"ok\"}}");
+            } else {
+                println!(
+                    "Successfully authenticated '{}' with the PAM service '{}'",
+                    auth_session.name, config.pam_service
+                );
+            }
+        }
+        Err(err) => {
+            if json {
+                println!("{}", cli::json_error(&err.to_string()));
+            } else {
+                eprintln!(
+                    "Failed to authenticate '{username}' with the PAM service '{}'. Reason: {err}",
+                    config.pam_service
+                );
+            }
+            ExitCode::Failure.exit();
+        }
+    }
+}
+
+/// Lock the terminal for the user that runs lemurs, until they type their password
+fn lock_screen(config: &Config, use_alternate_screen: bool) -> io::Result<()> {
+    let Some(username) = users::get_current_username() else {
+        eprintln!("Lemurs cannot determine the current user to lock the screen for");
+        ExitCode::Failure.exit();
+    };
+    let username = username.to_string_lossy();
+
+    if let Err(err) = signals::ignore_terminal_signals() {
+        eprintln!("Lemurs cannot lock the screen, since it cannot ignore Ctrl-C. Reason: {err}");
+        ExitCode::Failure.exit();
+    }
+
+    let mut terminal = match tui_enable(use_alternate_screen) {
+        Ok(terminal) => terminal,
+        Err(err) => {
+            report_tui_failure(&err);
+            ExitCode::Terminal.exit();
+        }
+    };
+    let result = ui::run_lock_screen(&mut terminal, ui::TerminalEvents, &username, config);
+    tui_disable(terminal, use_alternate_screen)?;
+
+    result
+}
+
+/// Explain on the terminal itself why the UI could not be started
+///
+/// This is the first thing a user sees when lemurs does not work on their terminal, so it should
+/// point them in the right direction.
+fn report_tui_failure(err: &io::Error) {
+    // Whatever state the terminal was left in, it should be usable to read the message
+    let _ = disable_raw_mode();
+
+    let is_colored = unsafe { libc::isatty(libc::STDERR_FILENO) } == 1;
+    let label = |label: &str, color: u8| {
+        if is_colored {
+            format!("\x1b[1;{color}m{label}\x1b[0m")
+        } else {
+            label.to_string()
+        }
+    };
+
+    eprintln!(
+        "{} Lemurs couldn't initialize the terminal UI: {err}",
+        label("error:", 31)
+    );
+    eprintln!(
+        "{} Try running with TERM=linux or with the --no-altscreen flag",
+        label("hint:", 33)
+    );
+    eprintln!(
+        "{} Set `terminal_mode = \"basic\"` in the configuration for serial consoles",
+        label("hint:", 33)
+    );
+}
+
+pub fn tui_disable(
+    mut terminal: Terminal<CrosstermBackend<io::Stdout>>,
+    use_alternate_screen: bool,
+) -> io::Result<()> {
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    if use_alternate_screen {
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    }
     terminal.show_cursor()?;
 
     info!("Reset terminal environment");
@@ -213,6 +625,12 @@ struct Hooks<'a> {
 
 pub enum StartSessionError {
     AuthenticationError(AuthenticationError),
+    AlreadyLoggedIn,
+    LoginHookFailed,
+    /// Logins are disabled with `/etc/nologin`. Contains the message of the file.
+    NoLogin(String),
+    /// Lemurs is in maintenance mode. Contains the configured message.
+    Maintenance(String),
     EnvironmentStartError(EnvironmentStartError),
 }
 
@@ -228,6 +646,200 @@ impl From<AuthenticationError> for StartSessionError {
     }
 }
 
+/// The message from `/etc/nologin`, when logins are disabled
+///
+/// The message is shown as the status message, so it is put on a single line.
+fn nologin_message(path: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let message = content.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    Some(if message.is_empty() {
+        "The system is not accepting logins".to_string()
+    } else {
+        message
+    })
+}
+
+/// Switch to another tty while lemurs is running, e.g. to the tty of the session
+fn switch_tty(tty: u8) {
+    info!("Switching to tty {tty}");
+    if let Err(err) = unsafe { chvt::chvt(tty.into()) } {
+        warn!("Failed to switch to tty {tty}. Reason: {err}");
+    }
+}
+
+/// Whether logins are currently limited to the administrators
+fn is_in_maintenance(config: &MaintenanceConfig) -> bool {
+    config.enabled || (!config.file.is_empty() && Path::new(&config.file).exists())
+}
+
+/// Whether a user can still login while lemurs is in maintenance mode
+fn is_maintenance_exempt(username: &str, config: &MaintenanceConfig) -> bool {
+    config.admins.iter().any(|admin| admin == username)
+        || matches!(users::get_user_by_name(username), Some(user) if user.uid() == 0)
+}
+
+/// Wait at most `timeout` for a child process to exit
+///
+/// Returns `None` when the process is still running after the `timeout`.
+fn wait_with_timeout(
+    child: &mut process::Child,
+    timeout: Duration,
+) -> io::Result<Option<process::ExitStatus>> {
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if started.elapsed() >= timeout {
+            return Ok(None);
+        }
+
+        thread::sleep(HOOK_POLL_INTERVAL);
+    }
+}
+
+/// Run the command of the `option` (e.g. `on_login_cmd`) as root for a user
+///
+/// When the command runs longer than the `timeout`, it is killed together with the processes it
+/// started. Returns whether the command ran successfully in time.
+fn run_user_hook(
+    option: &str,
+    command: &str,
+    username: &str,
+    timeout: Duration,
+    config: &Config,
+) -> bool {
+    let tty = config.tty.to_string();
+
+    info!("Running the {} for '{}'", option, username);
+    let child = process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .arg(format!("lemurs-{option}"))
+        .arg(username)
+        .arg(&tty)
+        .env("LEMURS_USERNAME", username)
+        .env("LEMURS_TTY", &tty)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::piped())
+        // Allows killing everything the command started at once
+        .process_group(0)
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            error!("Failed to run the {}. Reason: {}", option, err);
+            return false;
+        }
+    };
+
+    // A full pipe would block the command, so the output is read on the side
+    let stderr = child.stderr.take().map(|mut stderr| {
+        let (send, recv) = mpsc::channel();
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = stderr.read_to_end(&mut output);
+            let _ = send.send(output);
+        });
+        recv
+    });
+
+    let status = match wait_with_timeout(&mut child, timeout) {
+        Ok(Some(status)) => status,
+        Ok(None) => {
+            error!(
+                "The {} did not finish within {}s. Killing it",
+                option,
+                timeout.as_secs()
+            );
+            if let Err(err) = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL) {
+                warn!("Failed to kill the {}. Reason: {}", option, err);
+            }
+            let _ = child.wait();
+            return false;
+        }
+        Err(err) => {
+            error!("Failed to wait for the {}. Reason: {}", option, err);
+            return false;
+        }
+    };
+
+    if status.success() {
+        return true;
+    }
+
+    // Processes started in the background by the command may keep the output open
+    let stderr = stderr
+        .and_then(|output| output.recv_timeout(HOOK_OUTPUT_TIMEOUT).ok())
+        .unwrap_or_default();
+    error!(
+        "The {} failed with {}. STDERR: \"\"\"\n{}\n\"\"\"",
+        option,
+        status,
+        String::from_utf8_lossy(&stderr).trim()
+    );
+    false
+}
+
+/// A step in the authentication of a user, which is reported to the `auth_state_cmd`
+#[derive(Debug, Clone, Copy)]
+enum AuthState {
+    Started,
+    Failed,
+    Succeeded,
+}
+
+impl AuthState {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Started => "started",
+            Self::Failed => "failed",
+            Self::Succeeded => "succeeded",
+        }
+    }
+}
+
+/// Run the `auth_state_cmd` as root in the background
+fn run_auth_state_hook(state: AuthState, username: &str, config: &Config) {
+    if config.auth_state_cmd.is_empty() {
+        return;
+    }
+
+    let state = state.as_str();
+    let child = process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(&config.auth_state_cmd)
+        .arg("lemurs-auth-state")
+        .arg(state)
+        .arg(username)
+        .env("LEMURS_AUTH_STATE", state)
+        .env("LEMURS_USERNAME", username)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            error!("Failed to run the auth_state_cmd. Reason: {}", err);
+            return;
+        }
+    };
+
+    // The login should never wait for the command, but it still has to be reaped
+    std::thread::spawn(move || match child.wait() {
+        Ok(status) if !status.success() => {
+            warn!("The auth_state_cmd for '{state}' failed with {status}")
+        }
+        Ok(_) => {}
+        Err(err) => error!("Failed to wait for the auth_state_cmd. Reason: {}", err),
+    });
+}
+
 fn start_session(
     username: &str,
     password: &str,
@@ -235,15 +847,31 @@ fn start_session(
     hooks: &Hooks<'_>,
     config: &Config,
 ) -> Result<(), StartSessionError> {
+    let log_context = log_context::LoginContext::start(username);
     info!(
-        "Starting new session for '{}' in environment '{:?}'",
-        username, post_login_env
+        "Starting new session {} for '{}' in environment '{:?}'",
+        log_context.session_id(),
+        username,
+        post_login_env
     );
 
     if let Some(pre_validate_hook) = hooks.pre_validate {
         pre_validate_hook();
     }
 
+    // Refuse the login before PAM is involved at all
+    if is_in_maintenance(&config.maintenance)
+        && !is_maintenance_exempt(username, &config.maintenance)
+    {
+        warn!(
+            "Refusing login for '{}', because lemurs is in maintenance mode",
+            username
+        );
+        return Err(StartSessionError::Maintenance(
+            config.maintenance.message.clone(),
+        ));
+    }
+
     let mut process_env = EnvironmentContainer::take_snapshot();
 
     if let Some(pre_auth_hook) = hooks.pre_auth {
@@ -253,13 +881,68 @@ fn start_session(
     set_display(&mut process_env);
     set_session_params(&mut process_env, post_login_env);
 
-    let auth_session = try_auth(username, password, &config.pam_service)?;
+    // Opening the PAM session also sets the environment variables from the PAM modules, which are
+    // passed on to the session
+    run_auth_state_hook(AuthState::Started, username, config);
+    let auth_session = match try_auth(username, password, &config.pam_service) {
+        Ok(auth_session) => {
+            run_auth_state_hook(AuthState::Succeeded, username, config);
+            auth_session
+        }
+        Err(err) => {
+            run_auth_state_hook(AuthState::Failed, username, config);
+            return Err(err.into());
+        }
+    };
+    if let Ok(ccache) = std::env::var("KRB5CCNAME") {
+        info!("Using the Kerberos ticket cache '{ccache}' from PAM");
+    }
+
+    // Just like `login`, root can still login for maintenance
+    if auth_session.uid != 0 {
+        if let Some(message) = nologin_message(Path::new(NOLOGIN_PATH)) {
+            warn!(
+                "Refusing login for '{}', because '{}' exists",
+                username, NOLOGIN_PATH
+            );
+            return Err(StartSessionError::NoLogin(message));
+        }
+    }
+
+    if !config.allow_multiple_sessions && auth::utmpx::has_active_session(username) {
+        warn!(
+            "Refusing login for '{}', because they already have a session",
+            username
+        );
+        return Err(StartSessionError::AlreadyLoggedIn);
+    }
+
+    if !config.on_login_cmd.is_empty()
+        && !run_user_hook(
+            "on_login_cmd",
+            &config.on_login_cmd,
+            username,
+            Duration::from_secs(config.on_login_timeout),
+            config,
+        )
+        && config.on_login_strict
+    {
+        warn!(
+            "Refusing login for '{}', because the on_login_cmd failed",
+            username
+        );
+        return Err(StartSessionError::LoginHookFailed);
+    }
 
     if let Some(pre_environment_hook) = hooks.pre_environment {
         pre_environment_hook();
     }
 
-    let tty = config.tty;
+    let lemurs_tty = config
+        .tty
+        .number()
+        .expect("The tty is determined before the login form is shown");
+    let tty = config.session_vt().unwrap_or(lemurs_tty);
     let uid = auth_session.uid;
     let homedir = &auth_session.dir;
     let shell = &auth_session.shell;
@@ -268,12 +951,30 @@ fn start_session(
     set_session_vars(&mut process_env, uid);
     set_basic_variables(&mut process_env, username, homedir, shell);
     set_xdg_common_paths(&mut process_env, homedir);
+    if config.provide_lock_command {
+        set_lock_command(&mut process_env);
+    }
+    set_file_variables(&mut process_env, &config.environment_file);
+
+    if tty != lemurs_tty {
+        switch_tty(tty);
+    }
 
-    let spawned_environment = post_login_env.spawn(&auth_session, &mut process_env, config)?;
+    let spawned_environment = match post_login_env.spawn(&auth_session, &mut process_env, config) {
+        Ok(spawned_environment) => spawned_environment,
+        Err(err) => {
+            if tty != lemurs_tty {
+                switch_tty(lemurs_tty);
+            }
+            return Err(err.into());
+        }
+    };
 
     let pid = spawned_environment.pid();
 
     let utmpx_session = add_utmpx_entry(username, tty, pid);
+    let pid_file = SessionPidFile::write(&config.session_pid_file, pid, &auth_session);
+    let active_session = status_socket::ActiveSession::new(username);
     drop(process_env);
 
     info!("Waiting for environment to terminate");
@@ -282,16 +983,73 @@ fn start_session(
         pre_wait_hook();
     }
 
-    spawned_environment.wait();
+    signals::set_session_active(true);
+    spawned_environment.wait(Duration::from_secs(config.session_terminate_timeout));
+    signals::set_session_active(false);
 
     info!("Environment terminated. Returning to Lemurs...");
 
+    if tty != lemurs_tty {
+        switch_tty(lemurs_tty);
+    }
+
     if let Some(pre_return_hook) = hooks.pre_return {
         pre_return_hook();
     }
 
+    drop(active_session);
+    drop(pid_file);
     drop(utmpx_session);
     drop(auth_session);
 
+    // The session is completely closed, so this can clean up after it
+    if !config.on_logout_cmd.is_empty() {
+        // A failure is already logged, and the user is logged out either way
+        run_user_hook(
+            "on_logout_cmd",
+            &config.on_logout_cmd,
+            username,
+            Duration::from_secs(config.on_logout_timeout),
+            config,
+        );
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_hook_timeout() {
+        let config = Config::default();
+        let timeout = Duration::from_millis(200);
+
+        assert!(run_user_hook(
+            "on_login_cmd",
+            "true",
+            "lemur",
+            timeout,
+            &config
+        ));
+        assert!(!run_user_hook(
+            "on_login_cmd",
+            "exit 1",
+            "lemur",
+            timeout,
+            &config
+        ));
+
+        // The command is killed instead of blocking the login
+        let started = Instant::now();
+        assert!(!run_user_hook(
+            "on_login_cmd",
+            "sleep 10",
+            "lemur",
+            timeout,
+            &config
+        ));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
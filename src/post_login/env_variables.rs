@@ -1,13 +1,39 @@
-use log::info;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use log::{info, warn};
 
 use crate::env_container::EnvironmentContainer;
 
 use super::PostLoginEnvironment;
 
+/// The first display number that is tried for the X server
+const FIRST_DISPLAY: u32 = 1;
+/// The number of display numbers that are tried before giving up
+const MAX_DISPLAY_ATTEMPTS: u32 = 64;
+
+/// Whether an X server is already using a display number
+fn is_display_taken(display: u32) -> bool {
+    Path::new(&format!("/tmp/.X{display}-lock")).exists()
+        || Path::new(&format!("/tmp/.X11-unix/X{display}")).exists()
+}
+
+/// Find the first display number that is not in use by another X server
+fn first_free_display(is_taken: impl Fn(u32) -> bool) -> Option<u32> {
+    (FIRST_DISPLAY..FIRST_DISPLAY + MAX_DISPLAY_ATTEMPTS).find(|display| !is_taken(*display))
+}
+
 pub fn set_display(process_env: &mut EnvironmentContainer) {
     info!("Setting Display");
 
-    process_env.set("DISPLAY", ":1");
+    let display = first_free_display(is_display_taken).unwrap_or_else(|| {
+        warn!("Failed to find a free display. Falling back to display {FIRST_DISPLAY}");
+        FIRST_DISPLAY
+    });
+
+    info!("Using display :{display}");
+    process_env.set("DISPLAY", format!(":{display}"));
 }
 
 pub fn set_session_params(
@@ -72,3 +98,204 @@ pub fn set_xdg_common_paths(process_env: &mut EnvironmentContainer, homedir: &st
     process_env.set("XDG_DATA_DIRS", "/usr/local/share:/usr/share");
     process_env.set("XDG_CONFIG_DIRS", "/etc/xdg");
 }
+
+/// Point the `LEMURS_LOCK_CMD` variable to the lock screen of this lemurs binary
+pub fn set_lock_command(process_env: &mut EnvironmentContainer) {
+    match std::env::current_exe() {
+        Ok(exe) => process_env.set("LEMURS_LOCK_CMD", format!("{} --lock", exe.display())),
+        Err(err) => warn!("Failed to find the lemurs binary for the lock command. Reason: {err}"),
+    }
+}
+
+/// Whether `key` can be used as the name of an environment variable in a shell
+fn is_variable_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace the `$VAR` and `${VAR}` references in `value`. Unknown variables are empty.
+fn expand_variables(value: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let (name, remainder) = if let Some(braced) = rest.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", rest),
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+
+        if is_variable_name(name) {
+            expanded.push_str(&lookup(name).unwrap_or_default());
+            rest = remainder;
+        } else {
+            // Not a reference, e.g. a lone `$`
+            expanded.push('$');
+        }
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Parse the `KEY=VALUE` lines of an environment file
+///
+/// Empty lines and comments are skipped, and malformed lines are skipped with a warning. Values
+/// within single quotes are taken literally. Other values can reference earlier variables of the
+/// file and the variables given by `lookup`.
+fn parse_environment_file(
+    content: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Vec<(String, String)> {
+    let mut variables: Vec<(String, String)> = Vec::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            warn!(
+                "Skipping line {} of the environment file. It has no '='",
+                i + 1
+            );
+            continue;
+        };
+
+        let key = key.trim();
+        if !is_variable_name(key) {
+            warn!(
+                "Skipping line {} of the environment file. '{}' is not a valid variable name",
+                i + 1,
+                key
+            );
+            continue;
+        }
+
+        let value = value.trim();
+        let value = if let Some(literal) = value
+            .strip_prefix('\'')
+            .and_then(|value| value.strip_suffix('\''))
+        {
+            literal.to_string()
+        } else {
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+
+            expand_variables(value, |name| {
+                variables
+                    .iter()
+                    .rev()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.clone())
+                    .or_else(|| lookup(name))
+            })
+        };
+
+        variables.push((key.to_string(), value));
+    }
+
+    variables
+}
+
+/// Set the variables from the environment file at `path`
+///
+/// These override the variables set by lemurs, but not the variables set by the PAM modules.
+pub fn set_file_variables(process_env: &mut EnvironmentContainer, path: &str) {
+    if path.is_empty() {
+        return;
+    }
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            info!("No environment file found at '{}'", path);
+            return;
+        }
+        Err(err) => {
+            warn!(
+                "Failed to read the environment file '{}'. Reason: {}",
+                path, err
+            );
+            return;
+        }
+    };
+
+    info!("Setting Variables from '{}'", path);
+
+    for (key, value) in parse_environment_file(&content, |name| std::env::var(name).ok()) {
+        if process_env.is_foreign(&key) {
+            info!(
+                "Skipped setting environment variable '{}'. It was set by PAM",
+                key
+            );
+            continue;
+        }
+
+        process_env.set(&key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_taken_displays() {
+        assert_eq!(first_free_display(|_| false), Some(1));
+        assert_eq!(first_free_display(|display| display <= 2), Some(3));
+        assert_eq!(first_free_display(|_| true), None);
+    }
+
+    #[test]
+    fn expands_variables() {
+        let lookup = |name: &str| (name == "HOME").then(|| "/home/lemur".to_string());
+
+        assert_eq!(expand_variables("$HOME/bin", lookup), "/home/lemur/bin");
+        assert_eq!(expand_variables("${HOME}bin", lookup), "/home/lemurbin");
+        assert_eq!(expand_variables("a$UNKNOWN:b", lookup), "a:b");
+        assert_eq!(expand_variables("costs 5$", lookup), "costs 5$");
+    }
+
+    #[test]
+    fn parses_environment_file() {
+        let content = "
+            # A comment
+            EDITOR=vim
+            export PATH=\"$HOME/bin:$PATH\"
+            PROMPT='$literal'
+            VISUAL=$EDITOR
+            not a variable
+            1ST=one
+        ";
+        let lookup = |name: &str| match name {
+            "HOME" => Some("/home/lemur".to_string()),
+            "PATH" => Some("/usr/bin".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(
+            parse_environment_file(content, lookup),
+            [
+                ("EDITOR".to_string(), "vim".to_string()),
+                ("PATH".to_string(), "/home/lemur/bin:/usr/bin".to_string()),
+                ("PROMPT".to_string(), "$literal".to_string()),
+                ("VISUAL".to_string(), "vim".to_string()),
+            ]
+        );
+    }
+}
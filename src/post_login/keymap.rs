@@ -0,0 +1,159 @@
+use std::fs;
+use std::process::{Command, Stdio};
+
+use log::{info, warn};
+
+/// The file in which systemd stores the keymap of the console
+const VCONSOLE_CONF_PATH: &str = "/etc/vconsole.conf";
+
+/// Console keymaps whose name does not start with the matching XKB layout
+const KNOWN_KEYMAPS: &[(&str, &str, &str)] = &[
+    ("uk", "gb", ""),
+    ("dvorak", "us", "dvorak"),
+    ("colemak", "us", "colemak"),
+    ("sg", "ch", "de"),
+    ("sf", "ch", "fr"),
+    ("fr_CH", "ch", "fr"),
+    ("la-latin1", "latam", ""),
+    ("fr-bepo", "fr", "bepo"),
+    ("jp106", "jp", ""),
+];
+
+/// An XKB layout with an optional variant, as passed to `setxkbmap`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XkbLayout {
+    pub layout: String,
+    pub variant: Option<String>,
+}
+
+/// The `KEYMAP` value of a `vconsole.conf` file
+fn parse_vconsole_keymap(content: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("KEYMAP=")?;
+        let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// The XKB layout that best matches a console keymap
+///
+/// Console keymaps and XKB layouts do not map one to one, so this only covers the common naming
+/// scheme of `<layout>[-<variant>]` (e.g. `de-latin1-nodeadkeys`) and a few well-known exceptions.
+fn xkb_layout(keymap: &str) -> Option<XkbLayout> {
+    let keymap = keymap
+        .rsplit('/')
+        .next()
+        .unwrap_or(keymap)
+        .trim_end_matches(".gz")
+        .trim_end_matches(".map");
+
+    if let Some((_, layout, variant)) = KNOWN_KEYMAPS.iter().find(|(name, ..)| *name == keymap) {
+        return Some(XkbLayout {
+            layout: layout.to_string(),
+            variant: (!variant.is_empty()).then(|| variant.to_string()),
+        });
+    }
+
+    let mut parts = keymap.split(['-', '_']);
+    let layout = parts.next()?;
+    if !(2..=3).contains(&layout.len()) || !layout.chars().all(|c| c.is_ascii_lowercase()) {
+        return None;
+    }
+
+    // Only variants that exist for most layouts are kept. The rest (e.g. `latin1`) are character
+    // sets of the console that do not matter to X.
+    let variant = parts
+        .find(|part| matches!(*part, "nodeadkeys" | "dvorak" | "colemak"))
+        .map(str::to_string);
+
+    Some(XkbLayout {
+        layout: layout.to_string(),
+        variant,
+    })
+}
+
+/// The XKB layout matching the keymap of the console, if it is known
+pub fn console_xkb_layout() -> Option<XkbLayout> {
+    let content = match fs::read_to_string(VCONSOLE_CONF_PATH) {
+        Ok(content) => content,
+        Err(err) => {
+            info!(
+                "Failed to read the console keymap from '{}'. Reason: {}",
+                VCONSOLE_CONF_PATH, err
+            );
+            return None;
+        }
+    };
+
+    let keymap = parse_vconsole_keymap(&content)?;
+    let layout = xkb_layout(&keymap);
+    if layout.is_none() {
+        warn!("No keyboard layout for X is known for the console keymap '{keymap}'");
+    }
+
+    layout
+}
+
+/// Apply the keyboard layout of the console to the running X server
+///
+/// This is a best effort, so that the layout a user typed their password with is also used in
+/// their session. Failures are only logged.
+pub fn apply_console_layout_to_x() {
+    let Some(XkbLayout { layout, variant }) = console_xkb_layout() else {
+        return;
+    };
+
+    info!(
+        "Applying the console keyboard layout '{}' (variant '{}') to X",
+        layout,
+        variant.as_deref().unwrap_or_default()
+    );
+
+    let mut command = Command::new("/usr/bin/setxkbmap");
+    command.arg("-layout").arg(&layout);
+    if let Some(variant) = &variant {
+        command.arg("-variant").arg(variant);
+    }
+
+    match command.stdout(Stdio::null()).stderr(Stdio::null()).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("Setting the keyboard layout of X failed with {}", status),
+        Err(err) => warn!("Failed to run `setxkbmap`. Reason: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(layout: &str, variant: Option<&str>) -> Option<XkbLayout> {
+        Some(XkbLayout {
+            layout: layout.to_string(),
+            variant: variant.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn vconsole_keymap() {
+        assert_eq!(
+            parse_vconsole_keymap("FONT=lat2-16\nKEYMAP=\"de-latin1\"\n"),
+            Some("de-latin1".to_string())
+        );
+        assert_eq!(parse_vconsole_keymap("KEYMAP=us"), Some("us".to_string()));
+        assert_eq!(parse_vconsole_keymap("FONT=lat2-16\nKEYMAP="), None);
+    }
+
+    #[test]
+    fn keymap_to_xkb() {
+        assert_eq!(xkb_layout("us"), layout("us", None));
+        assert_eq!(xkb_layout("de-latin1"), layout("de", None));
+        assert_eq!(
+            xkb_layout("de-latin1-nodeadkeys"),
+            layout("de", Some("nodeadkeys"))
+        );
+        assert_eq!(xkb_layout("uk"), layout("gb", None));
+        assert_eq!(xkb_layout("sg"), layout("ch", Some("de")));
+        assert_eq!(xkb_layout("fr.map.gz"), layout("fr", None));
+        assert_eq!(xkb_layout("Unknown-Keymap"), None);
+    }
+}
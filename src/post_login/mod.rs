@@ -1,7 +1,14 @@
 use log::{error, info, warn};
 use std::error::Error;
+use std::ffi::CString;
 use std::fmt::Display;
 use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
 use users::get_user_groups;
 
@@ -9,23 +16,28 @@ use std::os::unix::process::CommandExt;
 use std::process::{Child, Command, Stdio};
 
 use crate::auth::AuthUserInfo;
-use crate::config::{Config, ShellLoginFlag};
+use crate::config::{Config, SessionOutput, ShellLoginFlag};
 use crate::env_container::EnvironmentContainer;
 use crate::post_login::x::setup_x;
 
-use nix::unistd::{Gid, Uid};
+use nix::errno::Errno;
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::{Gid, Pid, Uid};
 
 use self::x::XSetupError;
 
 pub(crate) mod env_variables;
+mod keymap;
 mod x;
 
 const SYSTEM_SHELL: &str = "/bin/sh";
 
+const SESSION_TERMINATE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 const INITRCS_FOLDER_PATH: &str = "/etc/lemurs/wms";
 const WAYLAND_FOLDER_PATH: &str = "/etc/lemurs/wayland";
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PostLoginEnvironment {
     X { xinitrc_path: String },
     Wayland { script_path: String },
@@ -45,10 +57,103 @@ impl PostLoginEnvironment {
     //     // TODO: Implement properly
     //     ""
     // }
+
+    /// The argument that is given to `-c` of the shell which starts the session
+    fn shell_command(&self, user_shell: &str, config: &Config) -> String {
+        let command = match self {
+            Self::X { xinitrc_path } => format!("{} {}", "/etc/lemurs/xsetup.sh", xinitrc_path),
+            Self::Wayland { script_path } => wayland_command(script_path, config),
+            Self::Shell => user_shell.to_string(),
+        };
+
+        if config.session_wrapper.is_empty() {
+            return command;
+        }
+
+        let wrapper = config
+            .session_wrapper
+            .iter()
+            .map(|word| shell_quote(word))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("{wrapper} {command}")
+    }
+
+    /// A description of how the session is started, shown before logging in
+    ///
+    /// The login shell of the user is not known yet, so it is shown as a placeholder.
+    pub fn details(&self, config: &Config) -> Vec<(&'static str, String)> {
+        const USER_SHELL: &str = "<login shell>";
+
+        let kind = match self {
+            Self::X { .. } => "X11",
+            Self::Wayland { .. } => "Wayland",
+            Self::Shell => "TTY",
+        };
+        let script = match self {
+            Self::X { xinitrc_path } => xinitrc_path.clone(),
+            Self::Wayland { script_path } => script_path.clone(),
+            Self::Shell => "-".to_string(),
+        };
+
+        let shell = if config.use_login_shell {
+            USER_SHELL
+        } else {
+            SYSTEM_SHELL
+        };
+        let mut command = vec![shell];
+        if let Some(flag) = shell_login_flag(config) {
+            command.push(flag);
+        }
+        command.push("-c");
+        let command = format!(
+            "{} '{}'",
+            command.join(" "),
+            self.shell_command(USER_SHELL, config)
+        );
+
+        vec![
+            ("Type", kind.to_string()),
+            ("Script", script),
+            ("Command", command),
+        ]
+    }
+
+    /// The variants that can be chosen instead of this environment, sorted by name
+    ///
+    /// Only environments from a subdirectory of the wms or wayland folder have variants. Every other
+    /// environment has none.
+    pub fn variants(&self) -> Vec<(String, PostLoginEnvironment)> {
+        match self {
+            Self::X { xinitrc_path } => {
+                variants_of(xinitrc_path, INITRCS_FOLDER_PATH, "X", &|xinitrc_path| {
+                    Self::X { xinitrc_path }
+                })
+            }
+            Self::Wayland { script_path } => variants_of(
+                script_path,
+                WAYLAND_FOLDER_PATH,
+                "wayland",
+                &|script_path| Self::Wayland { script_path },
+            ),
+            Self::Shell => Vec::new(),
+        }
+    }
+}
+
+fn shell_login_flag(config: &Config) -> Option<&'static str> {
+    match config.shell_login_flag {
+        ShellLoginFlag::None => None,
+        ShellLoginFlag::Short => Some("-l"),
+        ShellLoginFlag::Long => Some("--login"),
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum EnvironmentStartError {
+    ScriptNotExecutable(String),
+    NoInteractiveShell(String),
+    WrapperNotFound(String),
     WaylandStart,
     XSetup(XSetupError),
     XStartEnv,
@@ -58,6 +163,18 @@ pub enum EnvironmentStartError {
 impl Display for EnvironmentStartError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::ScriptNotExecutable(path) => {
+                write!(f, "The session script '{path}' cannot be executed")
+            }
+            Self::NoInteractiveShell(shell) if shell.is_empty() => {
+                f.write_str("The user has no shell set to start a TTY session with")
+            }
+            Self::NoInteractiveShell(shell) => {
+                write!(f, "The shell '{shell}' does not allow the user to login")
+            }
+            Self::WrapperNotFound(program) => {
+                write!(f, "The session wrapper '{program}' cannot be found")
+            }
             Self::WaylandStart => f.write_str("Failed to start Wayland compositor"),
             Self::XSetup(err) => write!(f, "Failed to setup X11 server. Reason: '{err}'"),
             Self::XStartEnv => f.write_str("Failed to start X11 client"),
@@ -73,6 +190,50 @@ impl From<XSetupError> for EnvironmentStartError {
     }
 }
 
+/// Whether a shell from the passwd database can be used for an interactive login
+///
+/// Accounts that should not login (e.g. service accounts) often get `nologin` or `false` as their
+/// shell, which exits immediately.
+fn is_interactive_shell(shell: &str) -> bool {
+    let program = shell.rsplit('/').next().unwrap_or_default();
+    !shell.trim().is_empty() && program != "nologin" && program != "false"
+}
+
+/// Whether `program` is an executable file, either as a path or as a name in one of the folders of
+/// `search_path`
+fn is_program_available(program: &str, search_path: &str) -> bool {
+    let is_executable = |path: &Path| {
+        fs::metadata(path)
+            .map(|metadata| metadata.is_file() && metadata.mode() & 0o111 != 0)
+            .unwrap_or(false)
+    };
+
+    if program.contains('/') {
+        return is_executable(Path::new(program));
+    }
+
+    search_path
+        .split(':')
+        .filter(|folder| !folder.is_empty())
+        .any(|folder| is_executable(&Path::new(folder).join(program)))
+}
+
+/// Check that the configured session wrapper can be started
+fn verify_session_wrapper(config: &Config) -> Result<(), EnvironmentStartError> {
+    let Some(program) = config.session_wrapper.first() else {
+        return Ok(());
+    };
+
+    // The `PATH` of the session is already set in the environment of lemurs
+    let search_path = std::env::var("PATH").unwrap_or_default();
+    if is_program_available(program, &search_path) {
+        Ok(())
+    } else {
+        error!("The session wrapper '{program}' is not an executable in '{search_path}'");
+        Err(EnvironmentStartError::WrapperNotFound(program.clone()))
+    }
+}
+
 fn lower_command_permissions_to_user(
     mut command: Command,
     user_info: &AuthUserInfo<'_>,
@@ -99,6 +260,497 @@ fn lower_command_permissions_to_user(
     command
 }
 
+/// Start the command as the leader of a new session and process group
+///
+/// This way the whole session can be signalled at once. With `take_terminal`, the terminal on
+/// stdin becomes the controlling terminal of the new session, which is needed for job control
+/// (e.g. Ctrl-C) to work.
+///
+/// NOTE: This has to be called before lowering the permissions of the command, because taking the
+/// terminal from lemurs requires root.
+fn start_in_new_session(command: &mut Command, take_terminal: bool) {
+    unsafe {
+        command.pre_exec(move || {
+            nix::unistd::setsid()?;
+
+            if take_terminal && libc::ioctl(0, libc::TIOCSCTTY, 1) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+}
+
+/// Open the terminal of the session VT, when sessions do not run on the VT of lemurs
+///
+/// When this fails, the session falls back to the terminal of lemurs.
+fn open_session_terminal(config: &Config) -> Option<fs::File> {
+    let session_vt = config.session_vt()?;
+    if Some(session_vt) == config.tty.number() {
+        return None;
+    }
+
+    let path = format!("/dev/tty{session_vt}");
+    match fs::OpenOptions::new().read(true).write(true).open(&path) {
+        Ok(terminal) => Some(terminal),
+        Err(err) => {
+            warn!("Failed to open the session terminal '{path}'. Using the terminal of lemurs. Reason: '{err}'");
+            None
+        }
+    }
+}
+
+/// The standard input, output and error of a session that runs within the terminal
+fn terminal_stdio(config: &Config) -> (Stdio, Stdio, Stdio) {
+    let inherit = || (Stdio::inherit(), Stdio::inherit(), Stdio::inherit());
+
+    let Some(terminal) = open_session_terminal(config) else {
+        return inherit();
+    };
+
+    match (terminal.try_clone(), terminal.try_clone()) {
+        (Ok(stdin), Ok(stdout)) => (stdin.into(), stdout.into(), terminal.into()),
+        (Err(err), _) | (_, Err(err)) => {
+            warn!("Failed to share the session terminal. Using the terminal of lemurs. Reason: '{err}'");
+            inherit()
+        }
+    }
+}
+
+/// Terminate all processes in a process group
+///
+/// The processes first get the chance to exit with SIGTERM. When `has_exited` does not report that
+/// they are gone within the `timeout`, they are killed. The `name` describes the processes in the
+/// logs.
+fn terminate_process_group(
+    pgid: u32,
+    name: &str,
+    timeout: Duration,
+    mut has_exited: impl FnMut() -> bool,
+) {
+    let pgid = Pid::from_raw(pgid as i32);
+
+    match killpg(pgid, Signal::SIGTERM) {
+        Ok(()) => info!("Sent SIGTERM to the {name}"),
+        // All processes have already exited
+        Err(Errno::ESRCH) => return,
+        Err(err) => {
+            warn!("Failed to terminate the {name}. Reason: {err}");
+            return;
+        }
+    }
+
+    let mut waited = Duration::ZERO;
+    while waited < timeout {
+        thread::sleep(SESSION_TERMINATE_POLL_INTERVAL);
+        waited += SESSION_TERMINATE_POLL_INTERVAL;
+
+        if has_exited() {
+            info!("The {name} exited after {}ms", waited.as_millis());
+            return;
+        }
+    }
+
+    warn!(
+        "The {name} did not exit within {}s. Sending SIGKILL",
+        timeout.as_secs()
+    );
+    if let Err(err) = killpg(pgid, Signal::SIGKILL) {
+        warn!("Failed to kill the {name}. Reason: {err}");
+    }
+}
+
+/// Terminate the processes that are left in the process group of a session after it ended
+fn terminate_session_processes(pgid: u32, timeout: Duration) {
+    // Signal 0 only checks whether any process is left in the group
+    terminate_process_group(pgid, "processes left over by the session", timeout, || {
+        killpg(Pid::from_raw(pgid as i32), None).is_err()
+    });
+}
+
+/// Stop the X server, which runs in its own process group
+fn terminate_x_server(server: &mut Child, timeout: Duration) {
+    // The X server is a child of lemurs, so it has to be reaped to actually be gone
+    terminate_process_group(server.id(), "X server", timeout, || {
+        matches!(server.try_wait(), Ok(Some(_)))
+    });
+
+    if let Err(err) = server.wait() {
+        warn!("Failed to wait for the X server to exit. Reason: {err}");
+    }
+}
+
+/// The directory the session is started in
+fn session_working_directory<'a>(user_info: &'a AuthUserInfo<'_>, config: &'a Config) -> &'a str {
+    let working_directory = if config.session_working_directory.is_empty() {
+        &user_info.dir
+    } else {
+        &config.session_working_directory
+    };
+
+    if Path::new(working_directory).is_dir() {
+        working_directory
+    } else {
+        warn!(
+            "The session working directory '{working_directory}' does not exist. Using '/' instead"
+        );
+        "/"
+    }
+}
+
+/// Verify that a session script exists, is a regular file and is executable.
+///
+/// If the script starts with a shebang, the interpreter is also verified to be executable.
+fn verify_script(script_path: &str) -> Result<(), EnvironmentStartError> {
+    let not_executable = || EnvironmentStartError::ScriptNotExecutable(script_path.to_string());
+
+    let metadata = fs::metadata(script_path).map_err(|err| {
+        error!("Failed to find session script '{script_path}'. Reason: '{err}'");
+        not_executable()
+    })?;
+
+    if !metadata.is_file() {
+        error!("Session script '{script_path}' is not a regular file");
+        return Err(not_executable());
+    }
+
+    if metadata.mode() & 0o111 == 0 {
+        error!("Session script '{script_path}' is not executable");
+        return Err(not_executable());
+    }
+
+    let Ok(file) = fs::File::open(script_path) else {
+        // We cannot read the shebang. Leave it up to the shell to report any problems.
+        return Ok(());
+    };
+
+    let mut first_line = Vec::new();
+    if BufReader::new(file)
+        .read_until(b'\n', &mut first_line)
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    let Some(shebang) = first_line.strip_prefix(b"#!") else {
+        return Ok(());
+    };
+
+    let shebang = String::from_utf8_lossy(shebang);
+    let Some(interpreter) = shebang.split_whitespace().next() else {
+        error!("Session script '{script_path}' contains an empty shebang");
+        return Err(not_executable());
+    };
+
+    match fs::metadata(interpreter) {
+        Ok(metadata) if metadata.is_file() && metadata.mode() & 0o111 != 0 => Ok(()),
+        _ => {
+            error!(
+                "Session script '{script_path}' uses the interpreter '{interpreter}', which is not executable"
+            );
+            Err(not_executable())
+        }
+    }
+}
+
+/// Log every line of a session's output in the background, tagged with the session name
+fn log_output(session_name: String, output: impl Read + Send + 'static) {
+    thread::spawn(move || {
+        for line in BufReader::new(output).split(b'\n') {
+            match line {
+                Ok(line) => info!("[{session_name}] {}", String::from_utf8_lossy(&line)),
+                Err(err) => {
+                    warn!("Failed to read output of session '{session_name}'. Reason: '{err}'");
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Write the output of the session to the session log file in the home directory of the user
+///
+/// The file is opened by the session process after it dropped to the permissions of the user, so
+/// that the user cannot make lemurs overwrite or take over a file they do not own. When the file
+/// cannot be opened, the output goes to the lemurs log instead.
+///
+/// NOTE: This has to be called after lowering the permissions of the command.
+fn redirect_to_session_log(command: &mut Command, path: &Path) {
+    const OPEN_FAILED: &[u8] =
+        b"Failed to open the session log file. Writing the output to the lemurs log instead\n";
+
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        warn!(
+            "Invalid session log file '{}'. Writing the output to the lemurs log instead",
+            path.display()
+        );
+        return;
+    };
+
+    // Nothing may be allocated between forking and executing, so only plain system calls are used
+    unsafe {
+        command.pre_exec(move || {
+            let fd = libc::open(
+                c_path.as_ptr(),
+                libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC,
+                0o600,
+            );
+            if fd < 0 {
+                // The output is still piped to lemurs, so this ends up in the lemurs log
+                libc::write(
+                    libc::STDERR_FILENO,
+                    OPEN_FAILED.as_ptr().cast(),
+                    OPEN_FAILED.len(),
+                );
+                return Ok(());
+            }
+
+            libc::dup2(fd, libc::STDOUT_FILENO);
+            libc::dup2(fd, libc::STDERR_FILENO);
+            libc::close(fd);
+            Ok(())
+        });
+    }
+}
+
+/// As whom a session PID file is written and removed
+#[derive(Debug, Clone, Copy)]
+enum PidFileOwner {
+    /// The file is in a folder that only root can write to
+    Root,
+    /// The file is in the home directory of the user, so it is only accessed with the filesystem
+    /// permissions of the user
+    User { uid: Uid, gid: Gid },
+}
+
+impl PidFileOwner {
+    /// Run `f` with the filesystem permissions of the owner
+    ///
+    /// This only changes the filesystem uid and gid of the current thread, so the rest of lemurs
+    /// keeps running as root.
+    fn access<T>(self, f: impl FnOnce() -> T) -> T {
+        let Self::User { uid, gid } = self else {
+            return f();
+        };
+
+        let old_gid = nix::unistd::setfsgid(gid);
+        let old_uid = nix::unistd::setfsuid(uid);
+        let result = f();
+        nix::unistd::setfsuid(old_uid);
+        nix::unistd::setfsgid(old_gid);
+
+        result
+    }
+}
+
+/// Check that only root can create or replace files in the folder of a session PID file with an
+/// absolute path
+///
+/// Returns why the folder cannot be trusted otherwise.
+fn check_pid_file_folder(is_dir: bool, uid: u32, mode: u32) -> Result<(), &'static str> {
+    if !is_dir {
+        return Err("is not a directory");
+    }
+    if uid != 0 {
+        return Err("is not owned by root");
+    }
+    if mode & 0o022 != 0 {
+        return Err("is writable by other users than root");
+    }
+
+    Ok(())
+}
+
+/// A file that contains the PID of the running session, so external tools can monitor or signal
+/// it. The file is removed when this is dropped.
+pub struct SessionPidFile {
+    path: PathBuf,
+    /// Kept open, so that only the file that was written is removed and not one that replaced it
+    file: fs::File,
+    owner: PidFileOwner,
+}
+
+impl SessionPidFile {
+    /// Write the PID of the session to `path`. Relative paths are relative to the home directory
+    /// of the user and are written as the user. Absolute paths have to be in a folder that only
+    /// root can write to.
+    ///
+    /// Failing to write the file does not stop the session, so this only warns.
+    pub fn write(path: &str, pid: u32, user_info: &AuthUserInfo<'_>) -> Option<Self> {
+        if path.is_empty() {
+            return None;
+        }
+
+        let path = Path::new(path);
+        if !path.is_absolute() {
+            let owner = PidFileOwner::User {
+                uid: Uid::from_raw(user_info.uid),
+                gid: Gid::from_raw(user_info.gid),
+            };
+            return Self::create(Path::new(&user_info.dir).join(path), pid, owner);
+        }
+
+        let folder = path.parent().unwrap_or(Path::new("/"));
+        let checked = match fs::symlink_metadata(folder) {
+            Ok(metadata) => {
+                check_pid_file_folder(metadata.is_dir(), metadata.uid(), metadata.mode())
+            }
+            Err(_) => Err("does not exist"),
+        };
+        if let Err(reason) = checked {
+            warn!(
+                "Not writing session PID file '{}', because its folder {reason}",
+                path.display()
+            );
+            return None;
+        }
+
+        Self::create(path.to_path_buf(), pid, PidFileOwner::Root)
+    }
+
+    fn create(path: PathBuf, pid: u32, owner: PidFileOwner) -> Option<Self> {
+        let file = owner.access(|| {
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o644)
+                .custom_flags(libc::O_NOFOLLOW)
+                .open(&path)?;
+
+            if let Err(err) = writeln!(file, "{pid}") {
+                let _ = fs::remove_file(&path);
+                return Err(err);
+            }
+
+            Ok::<_, std::io::Error>(file)
+        });
+
+        match file {
+            Ok(file) => {
+                info!("Wrote the session PID {pid} to '{}'", path.display());
+                Some(Self { path, file, owner })
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to write session PID file '{}'. Reason: '{err}'",
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Remove the file, unless it is not the file that was written anymore
+    fn remove(&self) -> std::io::Result<bool> {
+        let written = self.file.metadata()?;
+        let current = fs::symlink_metadata(&self.path)?;
+        if (written.dev(), written.ino()) != (current.dev(), current.ino()) {
+            return Ok(false);
+        }
+
+        fs::remove_file(&self.path)?;
+        Ok(true)
+    }
+}
+
+impl Drop for SessionPidFile {
+    fn drop(&mut self) {
+        match self.owner.access(|| self.remove()) {
+            Ok(true) => {}
+            Ok(false) => warn!(
+                "Not removing session PID file '{}', because it was replaced",
+                self.path.display()
+            ),
+            Err(err) => warn!(
+                "Failed to remove session PID file '{}'. Reason: '{err}'",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+/// Spawn a session environment with its output redirected according to the configuration
+fn spawn_with_output(
+    mut command: Command,
+    session_name: &str,
+    user_info: &AuthUserInfo<'_>,
+    config: &Config,
+) -> std::io::Result<Child> {
+    match config.session_output {
+        SessionOutput::File => {
+            info!("Writing output of session '{session_name}' to the session log file");
+            let path = Path::new(&user_info.dir).join(&config.session_log_path);
+            redirect_to_session_log(&mut command, &path);
+            // Only used when the session log file cannot be opened
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        SessionOutput::None => {
+            command.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        SessionOutput::Log => {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+    }
+
+    let mut child = command.spawn()?;
+
+    if let Some(stdout) = child.stdout.take() {
+        log_output(session_name.to_string(), stdout);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        log_output(session_name.to_string(), stderr);
+    }
+
+    Ok(child)
+}
+
+/// Quote `word` for the shell, unless that is not needed
+fn shell_quote(word: &str) -> String {
+    let is_plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+
+    if is_plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// The command that starts the Wayland session of `script_path`, with the configured wrapper and
+/// arguments
+fn wayland_command(script_path: &str, config: &Config) -> String {
+    let session_args = config
+        .wayland
+        .session_args
+        .get(session_name(script_path))
+        .into_iter()
+        .flatten();
+
+    config
+        .wayland
+        .wrapper
+        .iter()
+        .map(String::as_str)
+        .chain([script_path])
+        .chain(config.wayland.args.iter().map(String::as_str))
+        .chain(session_args.map(String::as_str))
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The name of a session script as it is shown in the environment switcher
+fn session_name(script_path: &str) -> &str {
+    Path::new(script_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(script_path)
+}
+
 pub enum SpawnedEnvironment {
     X11 { server: Child, client: Child },
     Wayland(Child),
@@ -112,45 +764,35 @@ impl SpawnedEnvironment {
         }
     }
 
-    pub fn wait(self) {
-        let child = match self {
-            Self::X11 { client, .. } | Self::Wayland(client) | Self::Tty(client) => client,
+    /// Wait for the session to end and stop everything that it leaves behind
+    ///
+    /// Processes that do not exit within the `terminate_timeout` after SIGTERM are killed.
+    pub fn wait(self, terminate_timeout: Duration) {
+        let (mut child, server) = match self {
+            Self::X11 { client, server } => (client, Some(server)),
+            Self::Wayland(client) | Self::Tty(client) => (client, None),
         };
 
-        let child_output = match child.wait_with_output() {
-            Ok(output) => output,
+        // The output of the environment is handled in the background. See `spawn_with_output`.
+        let status = match child.wait() {
+            Ok(status) => status,
             Err(err) => {
                 error!("Failed to wait for environment to exit, Reason: '{}'", err);
                 return;
             }
         };
 
-        // Print the stdout if it is at all available
-        match std::str::from_utf8(&child_output.stdout) {
-            Ok(output) => {
-                if !output.trim().is_empty() {
-                    info!("Environment's stdout: \"\"\"\n{}\n\"\"\"", output.trim());
-                }
-            }
-            Err(err) => {
-                warn!("Failed to read STDOUT output as UTF-8. Reason: '{}'", err);
-            }
-        };
+        if !status.success() {
+            warn!("Environment came back with non-zero exit code. Status: {status}");
+        }
 
-        // Return the `stderr` if the child process did not exit correctly.
-        if !child_output.status.success() {
-            warn!("Environment came back with non-zero exit code.");
+        // Processes started by the session (e.g. from the window manager) would otherwise keep
+        // running after logging out. The session was started as its own process group.
+        terminate_session_processes(child.id(), terminate_timeout);
 
-            match std::str::from_utf8(&child_output.stderr) {
-                Ok(output) => {
-                    if !output.trim().is_empty() {
-                        warn!("Environment's stderr: \"\"\"\n{}\n\"\"\"", output.trim());
-                    }
-                }
-                Err(err) => {
-                    warn!("Failed to read STDERR output as UTF-8. Reason: '{}'", err);
-                }
-            };
+        // Otherwise, the X server keeps holding the VT
+        if let Some(mut server) = server {
+            terminate_x_server(&mut server, terminate_timeout);
         }
     }
 }
@@ -162,15 +804,38 @@ impl PostLoginEnvironment {
         process_env: &mut EnvironmentContainer,
         config: &Config,
     ) -> Result<SpawnedEnvironment, EnvironmentStartError> {
-        let shell_login_flag = match config.shell_login_flag {
-            ShellLoginFlag::None => None,
-            ShellLoginFlag::Short => Some("-l"),
-            ShellLoginFlag::Long => Some("--login"),
+        // The X11 and Wayland sessions do not need an interactive shell, but a TTY session is nothing
+        // more than that shell.
+        let has_interactive_shell = is_interactive_shell(&user_info.shell);
+        if matches!(self, PostLoginEnvironment::Shell) && !has_interactive_shell {
+            return Err(EnvironmentStartError::NoInteractiveShell(
+                user_info.shell.clone(),
+            ));
+        }
+
+        verify_session_wrapper(config)?;
+
+        let shell = if !config.use_login_shell {
+            SYSTEM_SHELL
+        } else if !has_interactive_shell {
+            warn!(
+                "User has no usable login shell set ('{}'). Falling back to '{SYSTEM_SHELL}'",
+                user_info.shell
+            );
+            SYSTEM_SHELL
+        } else {
+            &user_info.shell
         };
 
-        let mut client = lower_command_permissions_to_user(Command::new(SYSTEM_SHELL), user_info);
+        info!("Starting session through the '{shell}' shell");
+        // The X server owns the terminal for X11 sessions
+        let mut client = Command::new(shell);
+        start_in_new_session(&mut client, !matches!(self, PostLoginEnvironment::X { .. }));
+
+        let mut client = lower_command_permissions_to_user(client, user_info);
+        client.current_dir(session_working_directory(user_info, config));
 
-        if let Some(shell_login_flag) = shell_login_flag {
+        if let Some(shell_login_flag) = shell_login_flag(config) {
             client.arg(shell_login_flag);
         }
 
@@ -178,16 +843,19 @@ impl PostLoginEnvironment {
 
         match self {
             PostLoginEnvironment::X { xinitrc_path } => {
+                verify_script(xinitrc_path)?;
+
                 info!("Starting X11 session");
-                let server =
-                    setup_x(process_env, user_info).map_err(EnvironmentStartError::XSetup)?;
+                let server = setup_x(process_env, user_info, config)
+                    .map_err(EnvironmentStartError::XSetup)?;
 
-                let client = match client
-                    .arg(format!("{} {}", "/etc/lemurs/xsetup.sh", xinitrc_path))
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .spawn()
-                {
+                client.arg(self.shell_command(&user_info.shell, config));
+                let client = match spawn_with_output(
+                    client,
+                    session_name(xinitrc_path),
+                    user_info,
+                    config,
+                ) {
                     Ok(child) => child,
                     Err(err) => {
                         error!("Failed to start X11 environment. Reason '{}'", err);
@@ -198,31 +866,34 @@ impl PostLoginEnvironment {
                 Ok(SpawnedEnvironment::X11 { server, client })
             }
             PostLoginEnvironment::Wayland { script_path } => {
+                verify_script(script_path)?;
+
                 info!("Starting Wayland session");
-                let child = match client
-                    .arg(script_path)
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .spawn()
-                {
-                    Ok(child) => child,
-                    Err(err) => {
-                        error!("Failed to start Wayland Compositor. Reason '{err}'");
-                        return Err(EnvironmentStartError::WaylandStart);
-                    }
-                };
+                client.arg(self.shell_command(&user_info.shell, config));
+                // The compositor takes the terminal of its VT
+                if let Some(terminal) = open_session_terminal(config) {
+                    client.stdin(terminal);
+                }
+                let child =
+                    match spawn_with_output(client, session_name(script_path), user_info, config) {
+                        Ok(child) => child,
+                        Err(err) => {
+                            error!("Failed to start Wayland Compositor. Reason '{err}'");
+                            return Err(EnvironmentStartError::WaylandStart);
+                        }
+                    };
 
                 Ok(SpawnedEnvironment::Wayland(child))
             }
             PostLoginEnvironment::Shell => {
                 info!("Starting TTY shell");
 
-                let shell = &user_info.shell;
+                let (stdin, stdout, stderr) = terminal_stdio(config);
                 let child = match client
-                    .arg(shell)
-                    .stdout(Stdio::inherit())
-                    .stderr(Stdio::inherit())
-                    .stdin(Stdio::inherit())
+                    .arg(self.shell_command(&user_info.shell, config))
+                    .stdout(stdout)
+                    .stderr(stderr)
+                    .stdin(stdin)
                     .spawn()
                 {
                     Ok(child) => child,
@@ -238,101 +909,144 @@ impl PostLoginEnvironment {
     }
 }
 
-pub fn get_envs(with_tty_shell: bool) -> Vec<(String, PostLoginEnvironment)> {
-    // NOTE: Maybe we can do something smart with `with_capacity` here.
-    let mut envs = Vec::new();
+/// Environments that are shown in the preview when no environments are installed
+///
+/// These are never started, since the preview does not login.
+pub fn get_sample_envs() -> Vec<(String, PostLoginEnvironment)> {
+    vec![
+        (
+            "bspwm".to_string(),
+            PostLoginEnvironment::X {
+                xinitrc_path: format!("{INITRCS_FOLDER_PATH}/bspwm"),
+            },
+        ),
+        (
+            "sway".to_string(),
+            PostLoginEnvironment::Wayland {
+                script_path: format!("{WAYLAND_FOLDER_PATH}/sway"),
+            },
+        ),
+    ]
+}
 
-    match fs::read_dir(INITRCS_FOLDER_PATH) {
-        Ok(paths) => {
-            for path in paths {
-                if let Ok(path) = path {
-                    let file_name = path.file_name().into_string();
-
-                    if let Ok(file_name) = file_name {
-                        if let Ok(metadata) = path.metadata() {
-                            if std::os::unix::fs::MetadataExt::mode(&metadata) & 0o111 == 0 {
-                                warn!(
-                            "'{file_name}' is not executable and therefore not added as an environment",
-                        );
-
-                                continue;
-                            }
-                        }
+/// The environments in `folder`, which contains a script for every environment
+///
+/// Only executable files (or symlinks to them) are environments. With `allow_variants`, a
+/// subdirectory with scripts is a single environment named after the subdirectory, whose scripts
+/// are its variants. The first variant is started unless another one is chosen. Anything else
+/// cannot be started and is skipped.
+fn environments_in(
+    folder: &str,
+    kind: &str,
+    allow_variants: bool,
+    to_environment: &dyn Fn(String) -> PostLoginEnvironment,
+) -> Vec<(String, PostLoginEnvironment)> {
+    let mut envs = Vec::new();
 
-                        envs.push((
-                            file_name,
-                            PostLoginEnvironment::X {
-                                xinitrc_path: match path.path().to_str() {
-                                    Some(p) => p.to_string(),
-                                    None => {
-                                        warn!(
-                                    "Skipped item because it was impossible to convert to string"
-                                );
-                                        continue;
-                                    }
-                                },
-                            },
-                        ));
-                    } else {
-                        warn!("Unable to convert OSString to String");
-                    }
-                } else {
-                    warn!("Ignored errorinous path: '{}'", path.unwrap_err());
-                }
-            }
-        }
+    let paths = match fs::read_dir(folder) {
+        Ok(paths) => paths,
         Err(_) => {
-            warn!("Failed to read from the X folder '{}'", INITRCS_FOLDER_PATH);
+            warn!("Failed to read from the {kind} folder '{folder}'");
+            return envs;
         }
-    }
+    };
 
-    match fs::read_dir(WAYLAND_FOLDER_PATH) {
-        Ok(paths) => {
-            for path in paths {
-                if let Ok(path) = path {
-                    let file_name = path.file_name().into_string();
+    for path in paths {
+        let path = match path {
+            Ok(path) => path,
+            Err(err) => {
+                warn!("Ignored errorinous path: '{}'", err);
+                continue;
+            }
+        };
 
-                    if let Ok(file_name) = file_name {
-                        if let Ok(metadata) = path.metadata() {
-                            if std::os::unix::fs::MetadataExt::mode(&metadata) & 0o111 == 0 {
-                                warn!(
-                            "'{}' is not executable and therefore not added as an environment",
-                            file_name
-                        );
+        let Ok(file_name) = path.file_name().into_string() else {
+            warn!("Unable to convert OSString to String");
+            continue;
+        };
 
-                                continue;
-                            }
-                        }
+        let Some(script_path) = path.path().to_str().map(str::to_string) else {
+            warn!("Skipped item because it was impossible to convert to string");
+            continue;
+        };
 
-                        envs.push((
-                            file_name,
-                            PostLoginEnvironment::Wayland {
-                                script_path: match path.path().to_str() {
-                                    Some(p) => p.to_string(),
-                                    None => {
-                                        warn!(
-                                    "Skipped item because it was impossible to convert to string"
-                                );
-                                        continue;
-                                    }
-                                },
-                            },
-                        ));
-                    } else {
-                        warn!("Unable to convert OSString to String");
-                    }
-                } else {
-                    warn!("Ignored errorinous path: '{}'", path.unwrap_err());
+        // Follow symlinks, so that a symlink to a script is still an environment
+        if let Ok(metadata) = fs::metadata(path.path()) {
+            if metadata.is_dir() && allow_variants {
+                let mut variants = environments_in(&script_path, kind, false, to_environment);
+                variants.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+                match variants.into_iter().next() {
+                    Some((_, environment)) => envs.push((file_name, environment)),
+                    None => warn!(
+                        "'{file_name}' contains no variants and therefore not added as an environment"
+                    ),
                 }
+                continue;
+            }
+
+            if !metadata.is_file() {
+                warn!("'{file_name}' is not a file and therefore not added as an environment");
+                continue;
+            }
+
+            if metadata.mode() & 0o111 == 0 {
+                warn!("'{file_name}' is not executable and therefore not added as an environment");
+                continue;
             }
         }
-        Err(_) => {
-            warn!(
-                "Failed to read from the wayland folder '{}'",
-                WAYLAND_FOLDER_PATH
-            );
-        }
+
+        envs.push((file_name, to_environment(script_path)));
+    }
+
+    envs
+}
+
+/// The variants next to `script_path`, if it is a variant in a subdirectory of `folder`
+fn variants_of(
+    script_path: &str,
+    folder: &str,
+    kind: &str,
+    to_environment: &dyn Fn(String) -> PostLoginEnvironment,
+) -> Vec<(String, PostLoginEnvironment)> {
+    let Some(variant_folder) = Path::new(script_path).parent() else {
+        return Vec::new();
+    };
+    if variant_folder.parent() != Some(Path::new(folder)) {
+        return Vec::new();
     }
+    let Some(variant_folder) = variant_folder.to_str() else {
+        return Vec::new();
+    };
+
+    let mut variants = environments_in(variant_folder, kind, false, to_environment);
+    variants.sort_by(|(a, _), (b, _)| a.cmp(b));
+    variants
+}
+
+/// Remove the environments with a title in `disabled`
+fn remove_disabled(envs: &mut Vec<(String, PostLoginEnvironment)>, disabled: &[String]) {
+    envs.retain(|(title, _)| {
+        let is_disabled = disabled.contains(title);
+        if is_disabled {
+            info!("Hiding the disabled environment '{title}'");
+        }
+
+        !is_disabled
+    });
+}
+
+pub fn get_envs(with_tty_shell: bool, disabled: &[String]) -> Vec<(String, PostLoginEnvironment)> {
+    let mut envs = environments_in(INITRCS_FOLDER_PATH, "X", true, &|xinitrc_path| {
+        PostLoginEnvironment::X { xinitrc_path }
+    });
+    envs.extend(environments_in(
+        WAYLAND_FOLDER_PATH,
+        "wayland",
+        true,
+        &|script_path| PostLoginEnvironment::Wayland { script_path },
+    ));
+    remove_disabled(&mut envs, disabled);
 
     if envs.is_empty() || with_tty_shell {
         envs.push(("TTYSHELL".to_string(), PostLoginEnvironment::Shell));
@@ -340,3 +1054,272 @@ pub fn get_envs(with_tty_shell: bool) -> Vec<(String, PostLoginEnvironment)> {
 
     envs
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interactive_shells() {
+        assert!(is_interactive_shell("/bin/bash"));
+        assert!(is_interactive_shell("/usr/bin/zsh"));
+        assert!(!is_interactive_shell(""));
+        assert!(!is_interactive_shell("/usr/sbin/nologin"));
+        assert!(!is_interactive_shell("/sbin/nologin"));
+        assert!(!is_interactive_shell("/bin/false"));
+    }
+
+    #[test]
+    fn environment_details() {
+        let environment = PostLoginEnvironment::X {
+            xinitrc_path: "/etc/lemurs/wms/i3".to_string(),
+        };
+        let mut config = Config::default();
+
+        assert_eq!(
+            environment.details(&config),
+            [
+                ("Type", "X11".to_string()),
+                ("Script", "/etc/lemurs/wms/i3".to_string()),
+                (
+                    "Command",
+                    "/bin/sh -l -c '/etc/lemurs/xsetup.sh /etc/lemurs/wms/i3'".to_string()
+                ),
+            ]
+        );
+
+        config.use_login_shell = true;
+        config.shell_login_flag = ShellLoginFlag::None;
+        assert_eq!(
+            PostLoginEnvironment::Shell.details(&config)[2].1,
+            "<login shell> -c '<login shell>'"
+        );
+    }
+
+    #[test]
+    fn wayland_arguments() {
+        let mut config = Config::default();
+        assert_eq!(
+            wayland_command("/etc/lemurs/wayland/sway", &config),
+            "/etc/lemurs/wayland/sway"
+        );
+
+        config.wayland.wrapper = vec!["dbus-run-session".to_string(), "--".to_string()];
+        config.wayland.args = vec!["--debug".to_string()];
+        config.wayland.session_args.insert(
+            "sway".to_string(),
+            vec!["--config".to_string(), "my config".to_string()],
+        );
+        assert_eq!(
+            wayland_command("/etc/lemurs/wayland/sway", &config),
+            "dbus-run-session -- /etc/lemurs/wayland/sway --debug --config 'my config'"
+        );
+        assert_eq!(
+            wayland_command("/etc/lemurs/wayland/river", &config),
+            "dbus-run-session -- /etc/lemurs/wayland/river --debug"
+        );
+
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn session_log_redirect() {
+        let folder =
+            std::env::temp_dir().join(format!("lemurs-session-log-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("session.log");
+        fs::write(&path, "previous session\n").unwrap();
+
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg("echo out; echo err >&2");
+        redirect_to_session_log(&mut command, &path);
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let output = command.output().unwrap();
+
+        assert!(output.stdout.is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "out\nerr\n");
+
+        // Without the file, the output still arrives through the pipes
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg("echo out");
+        redirect_to_session_log(&mut command, &folder.join("missing").join("session.log"));
+        let output = command.output().unwrap();
+        assert_eq!(output.stdout, b"out\n");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("session log file"));
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn pid_file_folders() {
+        assert_eq!(check_pid_file_folder(true, 0, 0o755), Ok(()));
+        assert_eq!(
+            check_pid_file_folder(false, 0, 0o755),
+            Err("is not a directory")
+        );
+        assert_eq!(
+            check_pid_file_folder(true, 1000, 0o755),
+            Err("is not owned by root")
+        );
+        assert_eq!(
+            check_pid_file_folder(true, 0, 0o775),
+            Err("is writable by other users than root")
+        );
+        assert_eq!(
+            check_pid_file_folder(true, 0, 0o1777),
+            Err("is writable by other users than root")
+        );
+    }
+
+    #[test]
+    fn pid_file_removal() {
+        let folder = std::env::temp_dir().join(format!("lemurs-pid-file-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("session.pid");
+
+        let pid_file = SessionPidFile::create(path.clone(), 42, PidFileOwner::Root).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "42\n");
+        drop(pid_file);
+        assert!(!path.exists());
+
+        // A file that replaced the written one is left alone
+        let pid_file = SessionPidFile::create(path.clone(), 42, PidFileOwner::Root).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "other\n").unwrap();
+        drop(pid_file);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "other\n");
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn session_wrapper() {
+        let mut config = Config {
+            session_wrapper: vec!["systemd-run".to_string(), "--user".to_string()],
+            ..Config::default()
+        };
+        config.wayland.wrapper = vec!["dbus-run-session".to_string()];
+
+        let environment = PostLoginEnvironment::Wayland {
+            script_path: "/etc/lemurs/wayland/sway".to_string(),
+        };
+        assert_eq!(
+            environment.shell_command("/bin/bash", &config),
+            "systemd-run --user dbus-run-session /etc/lemurs/wayland/sway"
+        );
+        assert_eq!(
+            PostLoginEnvironment::Shell.shell_command("/bin/bash", &config),
+            "systemd-run --user /bin/bash"
+        );
+
+        assert!(is_program_available("sh", "/nonexistent:/bin"));
+        assert!(is_program_available("/bin/sh", ""));
+        assert!(!is_program_available("sh", "/nonexistent"));
+        assert!(!is_program_available(
+            "lemurs-missing-wrapper",
+            "/bin:/usr/bin"
+        ));
+    }
+
+    #[test]
+    fn disabled_environments() {
+        let mut envs = vec![
+            ("i3".to_string(), PostLoginEnvironment::Shell),
+            ("sway".to_string(), PostLoginEnvironment::Shell),
+            ("bspwm".to_string(), PostLoginEnvironment::Shell),
+        ];
+        remove_disabled(&mut envs, &["sway".to_string(), "gnome".to_string()]);
+
+        let titles = envs
+            .iter()
+            .map(|(title, _)| title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["i3", "bspwm"]);
+    }
+
+    #[test]
+    fn environments_skip_directories() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let folder = std::env::temp_dir().join(format!("lemurs-envs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("subdirectory")).unwrap();
+        fs::write(folder.join("i3"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(folder.join("i3"), fs::Permissions::from_mode(0o755)).unwrap();
+        symlink(folder.join("i3"), folder.join("i3-link")).unwrap();
+        symlink(folder.join("subdirectory"), folder.join("directory-link")).unwrap();
+
+        let mut names = environments_in(folder.to_str().unwrap(), "X", true, &|xinitrc_path| {
+            PostLoginEnvironment::X { xinitrc_path }
+        })
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["i3", "i3-link"]);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn environment_variants() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let folder = std::env::temp_dir().join(format!("lemurs-variants-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("i3")).unwrap();
+        for variant in ["i3/compositor", "i3/bare"] {
+            fs::write(folder.join(variant), "#!/bin/sh\n").unwrap();
+            fs::set_permissions(folder.join(variant), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let to_environment = |xinitrc_path| PostLoginEnvironment::X { xinitrc_path };
+        let script_path = |env: &PostLoginEnvironment| match env {
+            PostLoginEnvironment::X { xinitrc_path } => xinitrc_path.clone(),
+            _ => unreachable!(),
+        };
+
+        let folder_path = folder.to_str().unwrap();
+        let envs = environments_in(folder_path, "X", true, &to_environment);
+        assert_eq!(envs.len(), 1);
+        assert_eq!(envs[0].0, "i3");
+        let default_variant = script_path(&envs[0].1);
+        assert_eq!(default_variant, format!("{folder_path}/i3/bare"));
+
+        let variants = variants_of(&default_variant, folder_path, "X", &to_environment);
+        let names = variants
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["bare", "compositor"]);
+
+        // Environments directly in the folder have no variants
+        assert!(variants_of(
+            &format!("{folder_path}/sway"),
+            folder_path,
+            "X",
+            &to_environment
+        )
+        .is_empty());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn session_leader() {
+        let mut command = Command::new("sleep");
+        command.arg("5");
+        start_in_new_session(&mut command, false);
+
+        let mut child = command.spawn().unwrap();
+        let pid = Pid::from_raw(child.id() as i32);
+
+        let sid = nix::unistd::getsid(Some(pid));
+        let pgid = nix::unistd::getpgid(Some(pid));
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(sid, Ok(pid));
+        assert_eq!(pgid, Ok(pid));
+        assert_ne!(sid, nix::unistd::getsid(None));
+    }
+}
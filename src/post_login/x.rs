@@ -1,9 +1,12 @@
 use rand::Rng;
+use std::collections::VecDeque;
 use std::env;
 use std::error::Error;
 use std::fmt::Display;
 use std::fs::remove_file;
+use std::io::{BufRead, BufReader, Read};
 use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
 use std::{thread, time};
 
 use std::path::PathBuf;
@@ -11,19 +14,26 @@ use std::path::PathBuf;
 use log::{error, info};
 
 use crate::auth::AuthUserInfo;
+use crate::config::Config;
 use crate::env_container::EnvironmentContainer;
 
 const XSTART_TIMEOUT_SECS: u64 = 20;
 const XSTART_CHECK_INTERVAL_MILLIS: u64 = 100;
 
+/// The number of lines of the X server's output that are kept to report failures
+const XSERVER_OUTPUT_LINES: usize = 20;
+/// The number of bytes after which a single line of the X server's output is cut off
+const XSERVER_OUTPUT_LINE_LENGTH: usize = 512;
+
 #[derive(Debug, Clone)]
 pub enum XSetupError {
     DisplayEnvVar,
-    VTNREnvVar,
+    UnknownVt,
     FillingXAuth,
     InvalidUTF8Path,
     XServerStart,
-    XServerTimeout,
+    XServerTimeout(String),
+    XServerExited(String),
     XServerStatusCheck,
 }
 
@@ -31,11 +41,18 @@ impl Display for XSetupError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::DisplayEnvVar => f.write_str("`DISPLAY` is not set"),
-            Self::VTNREnvVar => f.write_str("`XDG_VTNR` is not set"),
+            Self::UnknownVt => f.write_str("The VT of lemurs is not known"),
             Self::FillingXAuth => f.write_str("Failed to fill `.Xauthority` file"),
             Self::InvalidUTF8Path => f.write_str("Path that is given is not valid UTF8"),
             Self::XServerStart => f.write_str("Failed to start X server binary"),
-            Self::XServerTimeout => f.write_str("Timeout while waiting for X server to start"),
+            Self::XServerTimeout(output) => write!(
+                f,
+                "Timeout while waiting for X server to start. X server output: \"\"\"\n{output}\n\"\"\""
+            ),
+            Self::XServerExited(output) => write!(
+                f,
+                "X server exited while starting. X server output: \"\"\"\n{output}\n\"\"\""
+            ),
             Self::XServerStatusCheck => f.write_str("Failed to check for X server status"),
         }
     }
@@ -43,6 +60,45 @@ impl Display for XSetupError {
 
 impl Error for XSetupError {}
 
+/// The last lines of output of a process
+///
+/// The process output is read in the background until the process closes it, only keeping the
+/// last [`XSERVER_OUTPUT_LINES`] lines.
+#[derive(Clone, Default)]
+struct OutputTail(Arc<Mutex<VecDeque<String>>>);
+
+impl OutputTail {
+    fn capture(&self, output: impl Read + Send + 'static) {
+        let tail = self.clone();
+
+        thread::spawn(move || {
+            for line in BufReader::new(output).split(b'\n') {
+                let Ok(mut line) = line else {
+                    break;
+                };
+
+                line.truncate(XSERVER_OUTPUT_LINE_LENGTH);
+                let line = String::from_utf8_lossy(&line).into_owned();
+
+                let Ok(mut lines) = tail.0.lock() else {
+                    break;
+                };
+                if lines.len() == XSERVER_OUTPUT_LINES {
+                    lines.pop_front();
+                }
+                lines.push_back(line);
+            }
+        });
+    }
+
+    fn get(&self) -> String {
+        match self.0.lock() {
+            Ok(lines) => lines.iter().cloned().collect::<Vec<_>>().join("\n"),
+            Err(_) => String::new(),
+        }
+    }
+}
+
 fn mcookie() -> String {
     // TODO: Verify that this is actually safe. Maybe just use the mcookie binary?? Is that always
     // available?
@@ -51,16 +107,24 @@ fn mcookie() -> String {
     format!("{cookie:032x}")
 }
 
+/// The command that starts the X server on the given display and VT
+fn x_server_command(display: &str, vtnr: u8) -> String {
+    format!("/usr/bin/X {display} vt{vtnr:02}")
+}
+
 pub fn setup_x(
     process_env: &mut EnvironmentContainer,
     user_info: &AuthUserInfo,
+    config: &Config,
 ) -> Result<Child, XSetupError> {
     use std::os::unix::process::CommandExt;
 
     info!("Start setup of X");
 
     let display_value = env::var("DISPLAY").map_err(|_| XSetupError::DisplayEnvVar)?;
-    let vtnr_value = env::var("XDG_VTNR").map_err(|_| XSetupError::VTNREnvVar)?;
+    // The X server has to run on the configured VT. Otherwise, it may take another VT and the
+    // session shows up there.
+    let vtnr = config.session_vt().ok_or(XSetupError::UnknownVt)?;
 
     // Setup xauth
     let xauth_dir =
@@ -93,23 +157,38 @@ pub fn setup_x(
     let xauth_path = xauth_path.to_str().ok_or(XSetupError::InvalidUTF8Path)?;
     process_env.set("XAUTHORITY", xauth_path);
 
-    let doubledigit_vtnr = if vtnr_value.len() == 1 {
-        format!("0{vtnr_value}")
-    } else {
-        vtnr_value
-    };
+    if config.x_start_delay != 0 {
+        info!(
+            "Waiting {}ms for the VT to settle before starting the X server",
+            config.x_start_delay
+        );
+        thread::sleep(time::Duration::from_millis(config.x_start_delay));
+    }
 
-    info!("Run X server");
-    let child = Command::new(super::SYSTEM_SHELL)
+    info!("Run X server on vt{vtnr:02}");
+    let mut command = Command::new(super::SYSTEM_SHELL);
+    command
         .arg("-c")
-        .arg(format!("/usr/bin/X {display_value} vt{doubledigit_vtnr}",))
-        .stdout(Stdio::null()) // TODO: Maybe this should be logged or something?
-        .stderr(Stdio::null()) // TODO: Maybe this should be logged or something?
-        .spawn()
-        .map_err(|err| {
-            error!("Starting X server failed. Reason: {}", err);
-            XSetupError::XServerStart
-        })?;
+        .arg(x_server_command(&display_value, vtnr))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // This allows stopping the X server together with anything the shell started
+    super::start_in_new_session(&mut command, false);
+
+    let mut child = command.spawn().map_err(|err| {
+        error!("Starting X server failed. Reason: {}", err);
+        XSetupError::XServerStart
+    })?;
+
+    // Keep reading the output for as long as the X server runs. Otherwise, the X server blocks
+    // once the pipes are full.
+    let output = OutputTail::default();
+    if let Some(stdout) = child.stdout.take() {
+        output.capture(stdout);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        output.capture(stderr);
+    }
 
     // Wait for XServer to boot-up
     let start_time = time::SystemTime::now();
@@ -122,8 +201,19 @@ pub fn setup_x(
                 std::process::exit(1);
             }
         } {
+            // The output of the X server is logged along with the returned error
             error!("Starting X timed out!");
-            return Err(XSetupError::XServerTimeout);
+            let timeout = time::Duration::from_secs(config.session_terminate_timeout);
+            super::terminate_x_server(&mut child, timeout);
+            return Err(XSetupError::XServerTimeout(output.get()));
+        }
+
+        if let Ok(Some(status)) = child.try_wait() {
+            // Give the output some time to be read
+            thread::sleep(time::Duration::from_millis(XSTART_CHECK_INTERVAL_MILLIS));
+
+            error!("X server exited while starting with {}", status);
+            return Err(XSetupError::XServerExited(output.get()));
         }
 
         match Command::new(super::SYSTEM_SHELL)
@@ -149,5 +239,20 @@ pub fn setup_x(
 
     info!("X server is running");
 
+    if config.console_keymap_to_x {
+        super::keymap::apply_console_layout_to_x();
+    }
+
     Ok(child)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn x_server_runs_on_lemurs_vt() {
+        assert_eq!(x_server_command(":1", 2), "/usr/bin/X :1 vt02");
+        assert_eq!(x_server_command(":2", 12), "/usr/bin/X :2 vt12");
+    }
+}
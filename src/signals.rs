@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+/// Set when a SIGUSR1 arrived and the configuration has not been reloaded yet
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Set when a SIGHUP arrived while no session was running, and it has not been handled yet
+static HANGUP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a session is running at the moment
+static SESSION_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_reload(_: libc::c_int) {
+    // Only async-signal-safe operations are allowed here, so the actual reload happens within the
+    // event loop.
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn request_hangup(_: libc::c_int) {
+    // Replacing lemurs would end a running session, so then only the configuration is reloaded
+    if SESSION_ACTIVE.load(Ordering::SeqCst) {
+        RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+    } else {
+        HANGUP_REQUESTED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Install the handlers for the signals that lemurs reacts to
+///
+/// Sending SIGUSR1 to lemurs will reload the configuration file. What SIGHUP does depends on the
+/// `sighup_action`, which is handled by the login form.
+pub fn install_handlers() -> nix::Result<()> {
+    let action = SigAction::new(
+        SigHandler::Handler(request_reload),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+
+    unsafe { sigaction(Signal::SIGUSR1, &action) }?;
+
+    let action = SigAction::new(
+        SigHandler::Handler(request_hangup),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+
+    unsafe { sigaction(Signal::SIGHUP, &action) }?;
+
+    Ok(())
+}
+
+/// Ignore the signals that the terminal sends for keys like Ctrl-C and Ctrl-Z
+///
+/// The raw mode of the terminal already turns these keys into normal input. This makes sure that
+/// the lock screen can also not be stopped when the raw mode is lost.
+pub fn ignore_terminal_signals() -> nix::Result<()> {
+    let action = SigAction::new(SigHandler::SigIgn, SaFlags::empty(), SigSet::empty());
+
+    for signal in [Signal::SIGINT, Signal::SIGQUIT, Signal::SIGTSTP] {
+        unsafe { sigaction(signal, &action) }?;
+    }
+
+    Ok(())
+}
+
+/// Returns whether a reload of the configuration was requested since the last call
+pub fn take_reload_request() -> bool {
+    RELOAD_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Returns whether a SIGHUP arrived without a running session since the last call
+pub fn take_hangup_request() -> bool {
+    HANGUP_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+/// Mark whether a session is running, which decides what a SIGHUP does
+pub fn set_session_active(is_active: bool) {
+    SESSION_ACTIVE.store(is_active, Ordering::SeqCst);
+}
@@ -0,0 +1,192 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+
+/// The only request that is answered
+const STATUS_REQUEST: &str = "status";
+
+/// How long a client gets to send its request, so that a stuck client cannot block the others
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The most bytes that are read of a request, so that a client cannot make lemurs buffer a huge
+/// line
+const MAX_REQUEST_LENGTH: u64 = 64;
+
+/// The user of the session that is running, if any
+static ACTIVE_SESSION: Mutex<Option<String>> = Mutex::new(None);
+
+fn active_session_guard() -> MutexGuard<'static, Option<String>> {
+    // The value is always valid, even if a thread panicked while holding the lock
+    ACTIVE_SESSION
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Marks a session as running in the status socket, until this is dropped
+pub struct ActiveSession;
+
+impl ActiveSession {
+    pub fn new(username: &str) -> Self {
+        *active_session_guard() = Some(username.to_string());
+        Self
+    }
+}
+
+impl Drop for ActiveSession {
+    fn drop(&mut self) {
+        *active_session_guard() = None;
+    }
+}
+
+/// The answer to a request
+fn response(request: &str) -> String {
+    if request.trim() != STATUS_REQUEST {
+        return format!("unknown request, only '{STATUS_REQUEST}' is supported");
+    }
+
+    match active_session_guard().as_deref() {
+        Some(username) => format!("session active as {username}"),
+        None => "greeter up, no session".to_string(),
+    }
+}
+
+/// Answer the request of a single client, if it runs as one of the `allowed_uids`
+fn handle_client(stream: UnixStream, allowed_uids: &[u32]) -> io::Result<()> {
+    let uid = getsockopt(stream.as_raw_fd(), PeerCredentials)
+        .map_err(io::Error::from)?
+        .uid();
+    if !allowed_uids.contains(&uid) {
+        // Anyone can connect, so this is not a warning that could flood the log
+        debug!("Refused a status request from uid {uid}");
+        return Ok(());
+    }
+
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = String::new();
+    BufReader::new((&stream).take(MAX_REQUEST_LENGTH)).read_line(&mut request)?;
+
+    writeln!(&stream, "{}", response(&request))
+}
+
+/// Answer status requests on a Unix socket at `path`, so that monitoring can detect e.g. a stuck
+/// greeter
+///
+/// Every connection sends the line `status` and gets a single line back. Only root and the
+/// `allowed_users` are answered, based on the credentials of the connecting process.
+pub fn listen(path: &str, allowed_users: &[String]) {
+    let path = Path::new(path);
+
+    // A socket left behind by an earlier run would make binding fail
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if let Err(err) = fs::remove_file(path) {
+                warn!(
+                    "Failed to remove the old status socket '{}'. Reason: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+        Ok(_) => {
+            warn!(
+                "The status socket '{}' exists, but is not a socket. Not answering status requests",
+                path.display()
+            );
+            return;
+        }
+        Err(_) => {}
+    }
+
+    let listener = match UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            warn!(
+                "Failed to create the status socket '{}'. Reason: {}",
+                path.display(),
+                err
+            );
+            return;
+        }
+    };
+
+    // Everyone may connect, since the credentials of the client decide whether it is answered
+    if let Err(err) = fs::set_permissions(path, fs::Permissions::from_mode(0o666)) {
+        warn!(
+            "Failed to set the permissions of the status socket '{}'. Reason: {}",
+            path.display(),
+            err
+        );
+    }
+
+    let mut allowed_uids = vec![0];
+    for username in allowed_users {
+        match users::get_user_by_name(username) {
+            Some(user) => allowed_uids.push(user.uid()),
+            None => warn!("The status socket user '{username}' does not exist"),
+        }
+    }
+
+    info!("Answering status requests on '{}'", path.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| handle_client(stream, &allowed_uids));
+            if let Err(err) = result {
+                warn!("Failed to answer a status request. Reason: {}", err);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_requests() {
+        assert_eq!(response("status\n"), "greeter up, no session");
+
+        let session = ActiveSession::new("lemur");
+        assert_eq!(response("status\n"), "session active as lemur");
+        drop(session);
+
+        assert_eq!(response("status"), "greeter up, no session");
+        assert!(response("shutdown\n").starts_with("unknown request"));
+    }
+
+    #[test]
+    fn refuse_other_users() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let uid = users::get_current_uid();
+
+        // The other end of the pair runs as the current user
+        handle_client(server, &[uid + 1]).unwrap();
+
+        let mut answer = String::new();
+        (&client).read_to_string(&mut answer).unwrap();
+        assert_eq!(answer, "");
+    }
+
+    #[test]
+    fn long_requests() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let uid = users::get_current_uid();
+
+        // The request is cut off instead of waiting for the end of the line
+        write!(&client, "{}", "x".repeat(MAX_REQUEST_LENGTH as usize)).unwrap();
+        handle_client(server, &[uid]).unwrap();
+
+        let mut answer = String::new();
+        (&client).read_to_string(&mut answer).unwrap();
+        assert!(answer.starts_with("unknown request"));
+    }
+}
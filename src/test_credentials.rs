@@ -0,0 +1,67 @@
+//! Logging in with credentials from a named pipe, for automated end-to-end tests
+//!
+//! SECURITY: This is only compiled with the `insecure-test-credentials` feature and only used with
+//! the `--insecure-test-credentials <PIPE>` flag. Anyone that can write to the pipe can login as
+//! any user whose password they know, without being at the machine, and the passwords pass through
+//! the pipe in plain text. Never build lemurs with this feature for a real system.
+
+use std::sync::mpsc::Receiver;
+
+use log::warn;
+
+use crate::login_trigger::{listen_with, LoginTrigger};
+
+/// A line of the pipe, which contains `USERNAME<TAB>PASSWORD[<TAB>SESSION]`
+///
+/// Only the line ending is removed, so that the password may contain spaces.
+fn parse_credentials(line: &str) -> Option<LoginTrigger> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let mut parts = line.splitn(3, '\t');
+
+    let username = parts.next().filter(|username| !username.is_empty())?;
+    let Some(password) = parts.next() else {
+        warn!("Skipping test credentials without a password");
+        return None;
+    };
+    let session = parts.next().filter(|session| !session.is_empty());
+
+    Some(LoginTrigger {
+        username: username.to_string(),
+        password: Some(password.to_string()),
+        session: session.map(str::to_string),
+    })
+}
+
+/// Listen for credentials on the named pipe at `path`. Every line logs in through the login form,
+/// exactly like typing the credentials and pressing Enter.
+pub fn listen(path: &str) -> Option<Receiver<LoginTrigger>> {
+    warn!("INSECURE: Accepting login credentials from the pipe '{path}'. This is only meant for automated testing");
+    listen_with(path, parse_credentials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credential_lines() {
+        assert_eq!(
+            parse_credentials("lemur\tsecret with spaces \tsway\n"),
+            Some(LoginTrigger {
+                username: "lemur".to_string(),
+                password: Some("secret with spaces ".to_string()),
+                session: Some("sway".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_credentials("lemur\t"),
+            Some(LoginTrigger {
+                username: "lemur".to_string(),
+                password: Some(String::new()),
+                session: None,
+            })
+        );
+        assert_eq!(parse_credentials("lemur"), None);
+        assert_eq!(parse_credentials("\tsecret"), None);
+    }
+}
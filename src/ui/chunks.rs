@@ -5,43 +5,157 @@ use tui::{
 };
 use Constraint::{Length, Min};
 
+use super::InputMode;
+use crate::config::LayoutConfig;
+
 pub struct Chunks {
     pub power_menu: Rect,
     pub switcher: Rect,
     pub username_field: Rect,
     pub password_field: Rect,
     pub status_message: Rect,
+    pub motd: Rect,
+}
+
+/// The height of a field and the margin below it
+fn field_height(layout: &LayoutConfig, field: InputMode) -> (u16, u16) {
+    match field {
+        InputMode::Switcher => (1, layout.environment_switcher_margin),
+        InputMode::Username => (3, layout.username_field_margin),
+        InputMode::Password => (3, layout.password_field_margin),
+        InputMode::Normal => (0, 0),
+    }
+}
+
+/// The height of all the widgets and their margins with a certain layout
+fn total_height(layout: &LayoutConfig, fields: &[InputMode]) -> u32 {
+    let fields_height: u32 = fields
+        .iter()
+        .map(|field| {
+            let (height, margin) = field_height(layout, *field);
+            u32::from(height) + u32::from(margin)
+        })
+        .sum();
+
+    2 * u32::from(layout.vertical_margin)
+        + 1
+        + u32::from(layout.power_menu_margin)
+        + fields_height
+        + 1
 }
 
 impl Chunks {
-    pub fn new<B: Backend>(frame: &mut Frame<B>) -> Self {
-        let constraints = [
-            Length(1),
-            Length(1),
-            Length(2),
-            Length(1),
-            Length(2),
-            Length(3),
-            Length(2),
-            Length(3),
-            Length(2),
-            Length(1),
-            Min(0),
-        ];
+    /// Divide the screen for the `fields` that are shown. The other fields get an empty area.
+    pub fn new<B: Backend>(
+        frame: &mut Frame<B>,
+        layout: &LayoutConfig,
+        fields: &[InputMode],
+    ) -> Self {
+        let size = frame.size();
+
+        // Fall back to the default layout when the configured one does not fit on the screen
+        let default_layout;
+        let layout = if total_height(layout, fields) > u32::from(size.height)
+            || 2 * u32::from(layout.horizontal_margin) >= u32::from(size.width)
+        {
+            default_layout = LayoutConfig::default();
+            &default_layout
+        } else {
+            layout
+        };
+
+        let mut constraints = vec![Length(1), Length(layout.power_menu_margin)];
+        for field in fields {
+            let (height, margin) = field_height(layout, *field);
+            constraints.push(Length(height));
+            constraints.push(Length(margin));
+        }
+        constraints.extend([Length(1), Length(1), Min(0)]);
 
         let chunks = Layout::default()
             .direction(Direction::Vertical)
-            .horizontal_margin(2)
-            .vertical_margin(1)
-            .constraints(constraints.as_ref())
-            .split(frame.size());
+            .horizontal_margin(layout.horizontal_margin)
+            .vertical_margin(layout.vertical_margin)
+            .constraints(constraints)
+            .split(size);
 
-        Self {
+        let status_index = 2 + 2 * fields.len();
+        let mut result = Self {
             power_menu: chunks[0],
-            switcher: chunks[3],
-            username_field: chunks[5],
-            password_field: chunks[7],
-            status_message: chunks[9],
+            switcher: Rect::default(),
+            username_field: Rect::default(),
+            password_field: Rect::default(),
+            status_message: chunks[status_index],
+            motd: chunks[status_index + 2],
+        };
+
+        for (i, field) in fields.iter().enumerate() {
+            let area = chunks[2 + 2 * i];
+
+            match field {
+                InputMode::Switcher => result.switcher = area,
+                InputMode::Username => result.username_field = area,
+                InputMode::Password => result.password_field = area,
+                InputMode::Normal => {}
+            }
         }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tui::{backend::TestBackend, Terminal};
+
+    const ALL_FIELDS: [InputMode; 3] = InputMode::FIELDS;
+
+    fn chunks_for(width: u16, height: u16, layout: &LayoutConfig) -> (u16, u16) {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        let mut positions = (0, 0);
+
+        terminal
+            .draw(|f| {
+                let chunks = Chunks::new(f, layout, &ALL_FIELDS);
+                positions = (chunks.power_menu.x, chunks.switcher.y);
+            })
+            .unwrap();
+
+        positions
+    }
+
+    #[test]
+    fn default_layout() {
+        // Matches the layout from before the layout was configurable
+        assert_eq!(chunks_for(80, 24, &LayoutConfig::default()), (2, 5));
+    }
+
+    #[test]
+    fn oversized_layout_falls_back() {
+        let layout = LayoutConfig {
+            horizontal_margin: 4,
+            power_menu_margin: 30,
+            ..LayoutConfig::default()
+        };
+
+        assert_eq!(chunks_for(80, 60, &layout), (4, 32));
+        assert_eq!(chunks_for(80, 24, &layout), (2, 5));
+    }
+
+    #[test]
+    fn field_order() {
+        use InputMode::*;
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal
+            .draw(|f| {
+                let chunks = Chunks::new(f, &LayoutConfig::default(), &[Password, Switcher]);
+
+                assert_eq!(chunks.password_field.y, 5);
+                assert_eq!(chunks.switcher.y, 10);
+                assert_eq!(chunks.username_field, Rect::default());
+            })
+            .unwrap();
     }
 }
@@ -0,0 +1,94 @@
+use std::fmt;
+use std::io;
+
+use crossterm::execute;
+
+use crate::config::CursorShape;
+
+/// Sets the shape of the cursor and whether it blinks
+///
+/// This uses the DECSCUSR escape sequence directly, because the cursor shapes of crossterm do not
+/// allow choosing whether the cursor blinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetCursorStyle(u8);
+
+impl SetCursorStyle {
+    /// Go back to the cursor of the terminal
+    pub const RESET: Self = Self(0);
+
+    /// The cursor style from the configuration. `None` keeps the native cursor of the terminal.
+    pub fn from_config(shape: CursorShape, blink: bool) -> Option<Self> {
+        let steady = match shape {
+            CursorShape::Default => return None,
+            CursorShape::Block => 2,
+            CursorShape::Underline => 4,
+            CursorShape::Bar => 6,
+        };
+
+        // The blinking variant always comes right before the steady one
+        Some(Self(if blink { steady - 1 } else { steady }))
+    }
+}
+
+impl crossterm::Command for SetCursorStyle {
+    fn write_ansi(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        write!(f, "\x1b[{} q", self.0)
+    }
+
+    #[cfg(windows)]
+    fn execute_winapi(&self) -> crossterm::Result<()> {
+        Ok(())
+    }
+}
+
+/// Applies a cursor style, and goes back to the cursor of the terminal when dropped. This also
+/// happens when the UI stops because of an error or a panic.
+pub struct CursorStyleGuard {
+    style: Option<SetCursorStyle>,
+}
+
+impl CursorStyleGuard {
+    /// Apply `style`. `None` keeps the native cursor of the terminal.
+    pub fn apply(style: Option<SetCursorStyle>) -> io::Result<Self> {
+        if let Some(style) = style {
+            execute!(io::stdout(), style)?;
+        }
+
+        Ok(Self { style })
+    }
+}
+
+impl Drop for CursorStyleGuard {
+    fn drop(&mut self) {
+        if self.style.is_some() {
+            // Nothing can be done anymore when the terminal cannot be written to
+            let _ = execute!(io::stdout(), SetCursorStyle::RESET);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::Command;
+
+    fn ansi(style: SetCursorStyle) -> String {
+        let mut output = String::new();
+        style.write_ansi(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn cursor_styles() {
+        assert_eq!(
+            SetCursorStyle::from_config(CursorShape::Default, true),
+            None
+        );
+
+        let bar = SetCursorStyle::from_config(CursorShape::Bar, false).unwrap();
+        assert_eq!(ansi(bar), "\x1b[6 q");
+        let blinking_block = SetCursorStyle::from_config(CursorShape::Block, true).unwrap();
+        assert_eq!(ansi(blinking_block), "\x1b[1 q");
+        assert_eq!(ansi(SetCursorStyle::RESET), "\x1b[0 q");
+    }
+}
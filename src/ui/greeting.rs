@@ -0,0 +1,105 @@
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+
+use tui::layout::{Alignment, Rect};
+use tui::style::Style;
+use tui::text::Span;
+use tui::widgets::Paragraph;
+use tui::Frame;
+
+use crate::config::{get_color, get_modifiers, GreetingConfig};
+
+/// Gives the current hour, which can be replaced in tests
+type Clock = Arc<dyn Fn() -> Option<i32> + Send + Sync>;
+
+/// A greeting that depends on the time of day, shown in the top-right corner
+#[derive(Clone)]
+pub struct GreetingWidget {
+    config: GreetingConfig,
+    clock: Clock,
+}
+
+/// The current hour in the local timezone
+fn current_hour() -> Option<i32> {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm = MaybeUninit::<libc::tm>::uninit();
+
+    if unsafe { libc::localtime_r(&now, tm.as_mut_ptr()) }.is_null() {
+        return None;
+    }
+
+    Some(unsafe { tm.assume_init() }.tm_hour)
+}
+
+impl GreetingWidget {
+    pub fn new(config: GreetingConfig) -> Self {
+        Self {
+            config,
+            clock: Arc::new(current_hour),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_clock(config: GreetingConfig, clock: Clock) -> Self {
+        Self { config, clock }
+    }
+
+    /// The hour that the greeting is shown for, if it is shown at all
+    ///
+    /// The greeting needs to be redrawn when this changes, e.g. when noon passes.
+    pub fn hour(&self) -> Option<i32> {
+        if !self.config.show_greeting {
+            return None;
+        }
+
+        (self.clock)()
+    }
+
+    fn phrase(&self, hour: i32) -> &str {
+        match hour {
+            5..=11 => &self.config.morning,
+            12..=17 => &self.config.afternoon,
+            18..=21 => &self.config.evening,
+            _ => &self.config.night,
+        }
+    }
+
+    fn style(&self) -> Style {
+        let mut style = Style::default().fg(get_color(&self.config.color));
+
+        for modifier in get_modifiers(&self.config.modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        style
+    }
+
+    pub fn render(&self, frame: &mut Frame<impl tui::backend::Backend>, area: Rect) {
+        let Some(hour) = self.hour() else {
+            return;
+        };
+
+        let widget = Paragraph::new(Span::styled(self.phrase(hour), self.style()))
+            .alignment(Alignment::Right);
+
+        frame.render_widget(widget, area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn phrase_per_hour() {
+        let greeting = GreetingWidget::new(Config::default().greeting);
+
+        assert_eq!(greeting.phrase(4), "Good night");
+        assert_eq!(greeting.phrase(5), "Good morning");
+        assert_eq!(greeting.phrase(11), "Good morning");
+        assert_eq!(greeting.phrase(12), "Good afternoon");
+        assert_eq!(greeting.phrase(18), "Good evening");
+        assert_eq!(greeting.phrase(22), "Good night");
+    }
+}
@@ -1,4 +1,4 @@
-use crossterm::event::KeyCode;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use tui::{
     layout::Rect,
     style::Style,
@@ -17,6 +17,9 @@ pub enum InputFieldDisplayType {
     Echo,
     /// Always statically show a selected character
     Replace(String),
+    /// Show the selected character a fixed number of times when there is any content, which hides
+    /// the length of the content
+    Fixed(String, u16),
 }
 
 #[derive(Clone)]
@@ -110,14 +113,38 @@ impl InputFieldWidget {
 
     /// Returns what the displayed string should be
     fn show_string(&self) -> String {
-        use InputFieldDisplayType::{Echo, Replace};
+        use InputFieldDisplayType::{Echo, Fixed, Replace};
 
         match &self.display_type {
             Echo => self.show_echo(),
             Replace(s) => self.show_replace(s),
+            Fixed(s, count) => self.show_fixed(s, *count),
         }
     }
 
+    fn show_fixed(&self, replacement: &str, count: u16) -> String {
+        if self.content.is_empty() || replacement.width() == 0 {
+            return String::new();
+        }
+
+        let count = usize::min(
+            usize::from(count),
+            usize::from(self.width) / replacement.width(),
+        );
+        replacement.repeat(count)
+    }
+
+    /// The column of the cursor within the shown string
+    fn cursor_column(&self, show_string: &str) -> u16 {
+        // The cursor would give away the length of the content
+        if matches!(self.display_type, InputFieldDisplayType::Fixed(..)) {
+            return show_string.width() as u16;
+        }
+
+        let cursor_offset = get_byte_offset_of_char_offset(show_string, self.cursor.into());
+        show_string[..cursor_offset].width() as u16
+    }
+
     fn backspace(&mut self) {
         let cursor = usize::from(self.cursor);
         let scroll = usize::from(self.scroll);
@@ -201,6 +228,53 @@ impl InputFieldWidget {
         }
     }
 
+    /// The number of characters before the cursor
+    fn position(&self) -> usize {
+        usize::from(self.cursor) + usize::from(self.scroll)
+    }
+
+    /// Remove `count` characters before the cursor
+    fn delete_before(&mut self, count: usize) {
+        let position = self.position();
+        let start = position.saturating_sub(count);
+
+        let start_index = get_byte_offset_of_char_offset(&self.content, start);
+        let end_index = get_byte_offset_of_char_offset(&self.content, position);
+        self.content.replace_range(start_index..end_index, "");
+
+        // Keep the cursor in its column, so that as much as possible is shown before it. The start
+        // is at most the old position, so it fits in a u16.
+        let start = start as u16;
+        self.cursor = self.cursor.min(start);
+        self.scroll = start - self.cursor;
+    }
+
+    /// Remove everything before the cursor, like Ctrl-U in a shell
+    fn delete_line_before(&mut self) {
+        self.delete_before(self.position());
+    }
+
+    /// Remove the word before the cursor and the whitespace after it, like Ctrl-W in a shell
+    fn delete_word_before(&mut self) {
+        let mut before = self
+            .content
+            .chars()
+            .take(self.position())
+            .collect::<Vec<_>>();
+        let mut count = 0;
+
+        while matches!(before.last(), Some(c) if c.is_whitespace()) {
+            before.pop();
+            count += 1;
+        }
+        while matches!(before.last(), Some(c) if !c.is_whitespace()) {
+            before.pop();
+            count += 1;
+        }
+
+        self.delete_before(count);
+    }
+
     pub fn clear(&mut self) {
         self.cursor = 0;
         self.scroll = 0;
@@ -281,16 +355,25 @@ impl InputFieldWidget {
 
         if is_focused {
             let Rect { x, y, .. } = inner;
-            let cursor_offset = get_byte_offset_of_char_offset(&show_string, self.cursor.into());
-            frame.set_cursor(x + show_string[..cursor_offset].width() as u16, y);
+            frame.set_cursor(x + self.cursor_column(&show_string), y);
         }
     }
 
-    pub(crate) fn key_press(&mut self, key_code: KeyCode) -> Option<super::ErrorStatusMessage> {
-        match key_code {
+    pub(crate) fn key_press(&mut self, key: KeyEvent) -> Option<super::ErrorStatusMessage> {
+        let is_control = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key.code {
+            KeyCode::Char('u') if is_control => self.delete_line_before(),
+            KeyCode::Char('w') if is_control => self.delete_word_before(),
+            // Other shortcuts should not end up in the content
+            KeyCode::Char(_) if is_control => {}
+
             KeyCode::Backspace => self.backspace(),
             KeyCode::Delete => self.delete(),
 
+            // Moving through content that is not shown only causes confusion
+            KeyCode::Left | KeyCode::Right
+                if matches!(self.display_type, InputFieldDisplayType::Fixed(..)) => {}
             KeyCode::Left => self.left(),
             KeyCode::Right => self.right(),
 
@@ -310,6 +393,49 @@ impl InputFieldWidget {
         self.cursor = content.len() as u16;
         self.content = content.to_string();
     }
+
+    pub fn set_style(&mut self, style: InputFieldStyle) {
+        self.style = style;
+    }
+
+    pub fn set_display_type(&mut self, display_type: InputFieldDisplayType) {
+        self.display_type = display_type;
+    }
+}
+
+/// Why the content of a field and the field that confirms it are not accepted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfirmationError {
+    /// The field is empty or only contains whitespace
+    Empty,
+    /// The fields only differ in their trailing whitespace, which is easy to miss
+    TrailingWhitespace,
+    Mismatch,
+}
+
+/// Check that a field and its confirmation field (e.g. when choosing a new password) contain the
+/// same value, and return that value
+///
+/// The contents are compared exactly, since whitespace can be a valid part of a password.
+pub fn confirm_fields(
+    field: &InputFieldWidget,
+    confirmation: &InputFieldWidget,
+) -> Result<String, ConfirmationError> {
+    confirm_contents(&field.content, &confirmation.content)
+}
+
+fn confirm_contents(content: &str, confirmation: &str) -> Result<String, ConfirmationError> {
+    if content.trim().is_empty() {
+        return Err(ConfirmationError::Empty);
+    }
+
+    if content == confirmation {
+        Ok(content.to_string())
+    } else if content.trim_end() == confirmation.trim_end() {
+        Err(ConfirmationError::TrailingWhitespace)
+    } else {
+        Err(ConfirmationError::Mismatch)
+    }
 }
 
 #[cfg(test)]
@@ -318,6 +444,20 @@ mod tests {
     use crate::config::Config;
     use InputFieldDisplayType::*;
 
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    fn type_text(input_field: &mut InputFieldWidget, text: &str) {
+        for c in text.chars() {
+            input_field.key_press(key(KeyCode::Char(c)));
+        }
+    }
+
     #[test]
     fn cursor_movement() {
         // TODO: Verify Unicode behaviour
@@ -397,4 +537,117 @@ mod tests {
         input_field.backspace();
         assert_eq!(&input_field.show_string(), "");
     }
+
+    #[test]
+    fn fixed_length() {
+        let mut input_field = InputFieldWidget::new(
+            Fixed("*".to_string(), 4),
+            Config::default().password_field.style,
+            String::default(),
+        );
+
+        assert_eq!(&input_field.show_string(), "");
+        input_field.key_press(key(KeyCode::Char('x')));
+        assert_eq!(&input_field.show_string(), "****");
+        type_text(&mut input_field, "secret");
+        assert_eq!(&input_field.show_string(), "****");
+        assert_eq!(input_field.cursor_column("****"), 4);
+
+        // Moving does nothing, so typing always appends
+        input_field.key_press(key(KeyCode::Left));
+        input_field.key_press(key(KeyCode::Char('!')));
+        assert_eq!(input_field.get_content(), "xsecret!");
+    }
+
+    #[test]
+    fn line_editing() {
+        let mut input_field = InputFieldWidget::new(
+            Echo,
+            Config::default().username_field.style,
+            String::default(),
+        );
+        // As if it was rendered, so nothing has to scroll
+        input_field.width = 40;
+
+        type_text(&mut input_field, "one two  three  ");
+        input_field.key_press(ctrl('w'));
+        assert_eq!(input_field.get_content(), "one two  ");
+        input_field.key_press(ctrl('w'));
+        assert_eq!(input_field.get_content(), "one ");
+
+        // Only the word before the cursor is removed
+        type_text(&mut input_field, "two.three");
+        for _ in 0..3 {
+            input_field.key_press(key(KeyCode::Left));
+        }
+        input_field.key_press(ctrl('w'));
+        assert_eq!(input_field.get_content(), "one ree");
+
+        input_field.key_press(ctrl('u'));
+        assert_eq!(input_field.get_content(), "ree");
+        input_field.key_press(key(KeyCode::Right));
+        input_field.key_press(ctrl('u'));
+        assert_eq!(input_field.get_content(), "ee");
+        input_field.key_press(ctrl('w'));
+        assert_eq!(input_field.get_content(), "ee");
+
+        // Other shortcuts do nothing
+        input_field.key_press(ctrl('a'));
+        assert_eq!(input_field.get_content(), "ee");
+
+        // Removing scrolled content scrolls back
+        input_field.width = 4;
+        type_text(&mut input_field, " abcdefgh");
+        input_field.key_press(ctrl('w'));
+        assert_eq!(input_field.get_content(), " ee");
+        assert_eq!((input_field.cursor, input_field.scroll), (1, 0));
+    }
+
+    #[test]
+    fn confirmation() {
+        use ConfirmationError::*;
+
+        assert_eq!(
+            confirm_contents("hunter2", "hunter2"),
+            Ok("hunter2".to_string())
+        );
+        assert_eq!(confirm_contents(" a b ", " a b "), Ok(" a b ".to_string()));
+        assert_eq!(confirm_contents("", ""), Err(Empty));
+        assert_eq!(confirm_contents("  ", "  "), Err(Empty));
+        assert_eq!(
+            confirm_contents("hunter2", "hunter2 "),
+            Err(TrailingWhitespace)
+        );
+        assert_eq!(confirm_contents("hunter2", "hunter3"), Err(Mismatch));
+    }
+
+    #[test]
+    fn confirmation_fields() {
+        let field = |content: &str| {
+            let mut field = InputFieldWidget::new(
+                Replace("*".to_string()),
+                Config::default().password_field.style,
+                String::default(),
+            );
+            type_text(&mut field, content);
+            field
+        };
+
+        assert_eq!(
+            confirm_fields(&field("hunter2"), &field("hunter2")),
+            Ok("hunter2".to_string())
+        );
+        assert_eq!(
+            confirm_fields(&field(""), &field("")),
+            Err(ConfirmationError::Empty)
+        );
+        assert_eq!(
+            confirm_fields(&field("hunter2 "), &field("hunter2")),
+            Err(ConfirmationError::TrailingWhitespace)
+        );
+        assert_eq!(
+            confirm_fields(&field("hunter2"), &field("hunter3")),
+            Err(ConfirmationError::Mismatch)
+        );
+    }
 }
@@ -0,0 +1,201 @@
+use crossterm::event::KeyCode;
+use tui::layout::Rect;
+use tui::style::{Modifier, Style};
+use tui::text::Span;
+use tui::widgets::Paragraph;
+use tui::Frame;
+
+use crate::config::{get_color, get_modifiers, OnScreenKeyboardConfig};
+
+/// The rows of character keys, without and with shift
+const ROWS: [(&str, &str); 4] = [
+    ("1234567890", "!@#$%^&*()"),
+    ("qwertyuiop", "QWERTYUIOP"),
+    ("asdfghjkl-", "ASDFGHJKL_"),
+    ("zxcvbnm,./", "ZXCVBNM;:?"),
+];
+
+/// The keys in the bottom row
+const SPECIAL_KEYS: [KeyboardKey; 5] = [
+    KeyboardKey::Shift,
+    KeyboardKey::Space,
+    KeyboardKey::Backspace,
+    KeyboardKey::Tab,
+    KeyboardKey::Enter,
+];
+
+/// The number of lines the keyboard takes up
+pub const KEYBOARD_HEIGHT: u16 = ROWS.len() as u16 + 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyboardKey {
+    Char(char),
+    Shift,
+    Space,
+    Backspace,
+    Tab,
+    Enter,
+}
+
+impl KeyboardKey {
+    fn label(&self) -> String {
+        match self {
+            Self::Char(c) => format!(" {c} "),
+            Self::Shift => " Shift ".to_string(),
+            Self::Space => "     Space     ".to_string(),
+            Self::Backspace => " Bksp ".to_string(),
+            Self::Tab => " Tab ".to_string(),
+            Self::Enter => " Enter ".to_string(),
+        }
+    }
+}
+
+/// The area of every key when the keyboard is drawn in `area`
+fn key_layout(area: Rect, is_shifted: bool) -> Vec<(Rect, KeyboardKey)> {
+    let char_rows = ROWS.iter().map(|(lower, upper)| {
+        let chars = if is_shifted { upper } else { lower };
+        chars.chars().map(KeyboardKey::Char).collect::<Vec<_>>()
+    });
+
+    let mut keys = Vec::new();
+    for (y, row) in char_rows.chain([SPECIAL_KEYS.to_vec()]).enumerate() {
+        let y = area.y + y as u16;
+        if y >= area.bottom() {
+            break;
+        }
+
+        // Every key is followed by a space
+        let row_width: u16 = row.iter().map(|key| key.label().len() as u16 + 1).sum();
+        let mut x = area.x + area.width.saturating_sub(row_width) / 2;
+
+        for key in row {
+            let width = key.label().len() as u16;
+            if x + width > area.right() {
+                break;
+            }
+
+            keys.push((Rect::new(x, y, width, 1), key));
+            x += width + 1;
+        }
+    }
+
+    keys
+}
+
+/// A keyboard on the screen, so that touchscreens without a physical keyboard can login
+pub struct OnScreenKeyboardWidget {
+    is_shifted: bool,
+    /// The area the keyboard was last rendered in
+    area: Rect,
+    config: OnScreenKeyboardConfig,
+}
+
+impl OnScreenKeyboardWidget {
+    pub fn new(config: OnScreenKeyboardConfig) -> Self {
+        Self {
+            is_shifted: false,
+            area: Rect::default(),
+            config,
+        }
+    }
+
+    pub fn render(&mut self, frame: &mut Frame<impl tui::backend::Backend>, area: Rect) {
+        self.area = area;
+
+        let mut style = Style::default().fg(get_color(&self.config.color));
+        for modifier in get_modifiers(&self.config.modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        for (key_area, key) in key_layout(area, self.is_shifted) {
+            let key_style = if key == KeyboardKey::Shift && self.is_shifted {
+                style.add_modifier(Modifier::REVERSED)
+            } else {
+                style
+            };
+
+            let widget = Paragraph::new(Span::styled(key.label(), key_style));
+            frame.render_widget(widget, key_area);
+        }
+    }
+
+    /// The key that is pressed by clicking or touching the screen at `column` and `row`
+    pub fn click(&mut self, column: u16, row: u16) -> Option<KeyCode> {
+        let (_, key) = key_layout(self.area, self.is_shifted)
+            .into_iter()
+            .find(|(area, _)| {
+                (area.left()..area.right()).contains(&column)
+                    && (area.top()..area.bottom()).contains(&row)
+            })?;
+
+        // Shift only applies to the next character
+        let is_shifted = std::mem::take(&mut self.is_shifted);
+        Some(match key {
+            KeyboardKey::Char(c) => KeyCode::Char(c),
+            KeyboardKey::Shift => {
+                self.is_shifted = !is_shifted;
+                return None;
+            }
+            KeyboardKey::Space => KeyCode::Char(' '),
+            KeyboardKey::Backspace => KeyCode::Backspace,
+            KeyboardKey::Tab => KeyCode::Tab,
+            KeyboardKey::Enter => KeyCode::Enter,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn keyboard() -> OnScreenKeyboardWidget {
+        let mut keyboard = OnScreenKeyboardWidget::new(Config::default().on_screen_keyboard);
+        keyboard.area = Rect::new(0, 10, 80, KEYBOARD_HEIGHT);
+        keyboard
+    }
+
+    /// The position of the center of `key`
+    fn position_of(keyboard: &OnScreenKeyboardWidget, key: KeyboardKey) -> (u16, u16) {
+        let (area, _) = key_layout(keyboard.area, keyboard.is_shifted)
+            .into_iter()
+            .find(|(_, k)| *k == key)
+            .unwrap();
+
+        (area.x + area.width / 2, area.y)
+    }
+
+    #[test]
+    fn clicking_keys() {
+        let mut keyboard = keyboard();
+
+        let (column, row) = position_of(&keyboard, KeyboardKey::Char('q'));
+        assert_eq!(keyboard.click(column, row), Some(KeyCode::Char('q')));
+        assert_eq!(keyboard.click(0, 0), None);
+
+        let (column, row) = position_of(&keyboard, KeyboardKey::Enter);
+        assert_eq!(keyboard.click(column, row), Some(KeyCode::Enter));
+    }
+
+    #[test]
+    fn shift_applies_once() {
+        let mut keyboard = keyboard();
+
+        let (column, row) = position_of(&keyboard, KeyboardKey::Shift);
+        assert_eq!(keyboard.click(column, row), None);
+
+        let (column, row) = position_of(&keyboard, KeyboardKey::Char('Q'));
+        assert_eq!(keyboard.click(column, row), Some(KeyCode::Char('Q')));
+        assert_eq!(keyboard.click(column, row), Some(KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn keys_fit_in_area() {
+        let area = Rect::new(2, 3, 30, 2);
+
+        for (key_area, _) in key_layout(area, false) {
+            assert!(key_area.left() >= area.left() && key_area.right() <= area.right());
+            assert!(key_area.top() >= area.top() && key_area.bottom() <= area.bottom());
+        }
+    }
+}
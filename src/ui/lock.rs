@@ -0,0 +1,110 @@
+use std::io;
+
+use crossterm::event::{Event, KeyCode, KeyModifiers};
+use log::{info, warn};
+use tui::backend::Backend;
+use tui::layout::Alignment;
+use tui::style::{Modifier, Style};
+use tui::text::Span;
+use tui::widgets::Paragraph;
+use tui::Terminal;
+
+use super::chunks::Chunks;
+use super::input_field::InputFieldWidget;
+use super::status_message::{ErrorStatusMessage, InfoStatusMessage, StatusMessage};
+use super::{
+    input_field_style, password_display_type, EventSource, InputMode, EVENT_POLL_INTERVAL,
+};
+use crate::auth::verify_password;
+use crate::config::Config;
+
+/// Show a password-only screen until the password of `username` is typed
+///
+/// This locks the terminal that it runs in, without touching the session of the user. The screen
+/// uses the layout of the login form with only the password field.
+pub fn run_lock_screen<B: Backend>(
+    terminal: &mut Terminal<B>,
+    mut events: impl EventSource,
+    username: &str,
+    config: &Config,
+) -> io::Result<()> {
+    let mut password = InputFieldWidget::new(
+        password_display_type(config),
+        input_field_style(config, &config.password_field.style),
+        String::default(),
+    );
+    let mut status_message: Option<StatusMessage> = None;
+    // The password that is verified after the "Authenticating" status is drawn
+    let mut pending_attempt: Option<String> = None;
+    let title = format!("Locked by {username}");
+
+    loop {
+        terminal.draw(|f| {
+            let chunks = Chunks::new(f, &config.layout, &[InputMode::Password]);
+
+            let title = Paragraph::new(Span::styled(
+                title.as_str(),
+                Style::default().add_modifier(Modifier::BOLD),
+            ))
+            .alignment(Alignment::Center);
+            f.render_widget(title, chunks.power_menu);
+
+            password.render(f, chunks.password_field, true);
+            StatusMessage::render(
+                status_message.clone(),
+                f,
+                chunks.status_message,
+                0,
+                &config.status_message,
+            );
+        })?;
+
+        if let Some(attempt) = pending_attempt.take() {
+            match verify_password(username, &attempt, &config.pam_service) {
+                Ok(()) => {
+                    info!("Unlocked the terminal of '{username}'");
+                    return Ok(());
+                }
+                Err(err) => {
+                    status_message = Some(ErrorStatusMessage::AuthenticationError(err).into());
+                }
+            }
+            continue;
+        }
+
+        if events.is_exhausted() {
+            return Ok(());
+        }
+
+        let key = match events.poll(EVENT_POLL_INTERVAL) {
+            Ok(Some(Event::Key(key))) => key,
+            Ok(_) => continue,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            // Giving up would unlock the screen, so keep trying
+            Err(err) => {
+                warn!("Failed to read event. Reason: {}", err);
+                std::thread::sleep(EVENT_POLL_INTERVAL);
+                continue;
+            }
+        };
+
+        // Shortcuts (e.g. Ctrl-C) do nothing here. The password field handles the editing shortcuts
+        // (e.g. Ctrl-U) and never types the others.
+        if key.modifiers.contains(KeyModifiers::ALT) {
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Enter => {
+                status_message = Some(InfoStatusMessage::Authenticating.into());
+                pending_attempt = Some(password.get_content());
+                if config.password_field.clear_on_failure {
+                    password.clear();
+                }
+            }
+            _ => {
+                password.key_press(key);
+            }
+        }
+    }
+}
@@ -1,49 +1,86 @@
 use log::{error, info, warn};
 
+use std::any::Any;
+use std::collections::HashMap;
 use std::io;
-use std::sync::mpsc::channel;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc::{channel, RecvTimeoutError};
 use std::sync::{Arc, Mutex, MutexGuard};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::config::{Config, FocusBehaviour};
-use crate::info_caching::{get_cached_information, set_cache};
-use crate::post_login::PostLoginEnvironment;
-use crate::{start_session, Hooks, StartSessionError};
+use crate::auth::RememberedLogin;
+use crate::config::{
+    get_color, get_key, Config, FailureAction, FocusBehaviour, HangupAction, InputFieldStyle,
+    LayoutConfig, LoginField, MissingEnvironment, ScreenClearing, StatusMessageConfig,
+    SwitcherConfig, SwitcherEnterBehaviour, Tty,
+};
+use crate::info_caching::{FileCache, InfoCache};
+use crate::login_trigger::LoginTrigger;
+use crate::post_login::{EnvironmentStartError, PostLoginEnvironment};
+use crate::{reload_configuration, signals, start_session, Hooks, StartSessionError};
 use status_message::StatusMessage;
 
 use crossterm::cursor::MoveTo;
-use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
+    MouseButton, MouseEvent, MouseEventKind,
+};
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
 };
-use tui::backend::CrosstermBackend;
+use tui::buffer::Buffer;
+use tui::layout::{Alignment, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Paragraph, Widget};
 use tui::{backend::Backend, Frame, Terminal};
 
 mod chunks;
+mod cursor;
+mod greeting;
 mod input_field;
+mod keyboard;
+mod lock;
+mod motd;
 mod power_menu;
+mod session_details;
 mod status_message;
 mod switcher;
+mod variant_picker;
 
 use chunks::Chunks;
+use cursor::{CursorStyleGuard, SetCursorStyle};
+use greeting::GreetingWidget;
 use input_field::{InputFieldDisplayType, InputFieldWidget};
+use keyboard::{OnScreenKeyboardWidget, KEYBOARD_HEIGHT};
+pub use lock::run_lock_screen;
+use motd::MotdWidget;
 use power_menu::PowerMenuWidget;
+use session_details::SessionDetailsWidget;
 use status_message::{ErrorStatusMessage, InfoStatusMessage};
 use switcher::{SwitcherItem, SwitcherWidget};
+use variant_picker::VariantPickerWidget;
 
 #[derive(Clone)]
-struct LoginFormInputMode(Arc<Mutex<InputMode>>);
+struct LoginFormInputMode {
+    mode: Arc<Mutex<InputMode>>,
+    /// The fields that are shown, in the order they are moved through
+    fields: Vec<InputMode>,
+}
 
 impl LoginFormInputMode {
-    fn new(mode: InputMode) -> Self {
-        Self(Arc::new(Mutex::new(mode)))
+    fn new(fields: Vec<InputMode>) -> Self {
+        Self {
+            mode: Arc::new(Mutex::new(InputMode::Normal)),
+            fields,
+        }
     }
 
     fn get_guard(&self) -> MutexGuard<InputMode> {
-        let Self(mutex) = self;
-
-        match mutex.lock() {
+        match self.mode.lock() {
             Ok(guard) => guard,
             Err(err) => {
                 error!("Lock failed. Reason: {}", err);
@@ -57,14 +94,33 @@ impl LoginFormInputMode {
     }
 
     fn prev(&self) {
-        self.get_guard().prev()
+        self.get_guard().prev(&self.fields)
     }
     fn next(&self) {
-        self.get_guard().next()
+        self.get_guard().next(&self.fields)
     }
     fn set(&self, mode: InputMode) {
+        // Never go into a field that is not shown. Take the first shown field that usually comes
+        // after it instead.
+        let mode = if mode == InputMode::Normal || self.fields.contains(&mode) {
+            mode
+        } else {
+            InputMode::FIELDS
+                .iter()
+                .skip_while(|field| **field != mode)
+                .find(|field| self.fields.contains(field))
+                .or_else(|| self.fields.last())
+                .copied()
+                .unwrap_or(InputMode::Normal)
+        };
+
         *self.get_guard() = mode;
     }
+
+    /// Whether the current mode is the last field of the login form
+    fn is_last_field(&self) -> bool {
+        self.fields.last() == Some(&self.get())
+    }
 }
 
 #[derive(Clone)]
@@ -100,8 +156,8 @@ impl LoginFormStatusMessage {
 }
 
 /// All the different modes for input
-#[derive(Clone, Copy)]
-enum InputMode {
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum InputMode {
     /// Using the env switcher widget
     Switcher,
 
@@ -116,28 +172,123 @@ enum InputMode {
 }
 
 impl InputMode {
-    /// Move to the next mode
-    fn next(&mut self) {
-        use InputMode::*;
+    /// All the fields in their default order
+    const FIELDS: [InputMode; 3] = [
+        InputMode::Switcher,
+        InputMode::Username,
+        InputMode::Password,
+    ];
+
+    /// Move to the next field within `fields`. The last field is never left.
+    pub(crate) fn next(&mut self, fields: &[InputMode]) {
+        let next = match self.position(fields) {
+            Some(index) => fields.get(index + 1),
+            None if *self == InputMode::Normal => fields.first(),
+            None => None,
+        };
 
-        *self = match self {
-            Normal => Switcher,
-            Switcher => Username,
-            Username => Password,
-            Password => Password,
+        if let Some(next) = next {
+            *self = *next;
         }
     }
 
-    /// Move to the previous mode
-    fn prev(&mut self) {
-        use InputMode::*;
+    /// Move to the previous field within `fields`. Before the first field comes `Normal`.
+    pub(crate) fn prev(&mut self, fields: &[InputMode]) {
+        match self.position(fields) {
+            Some(0) => *self = InputMode::Normal,
+            Some(index) => *self = fields[index - 1],
+            None => {}
+        }
+    }
+
+    fn position(self, fields: &[InputMode]) -> Option<usize> {
+        fields.iter().position(|field| *field == self)
+    }
+
+    /// The name of the field in the breadcrumb
+    fn label(self) -> &'static str {
+        match self {
+            InputMode::Switcher => "Session",
+            InputMode::Username => "Username",
+            InputMode::Password => "Password",
+            InputMode::Normal => "Menu",
+        }
+    }
+}
+
+impl From<LoginField> for InputMode {
+    fn from(field: LoginField) -> Self {
+        match field {
+            LoginField::Environment => InputMode::Switcher,
+            LoginField::Username => InputMode::Username,
+            LoginField::Password => InputMode::Password,
+        }
+    }
+}
+
+/// The time between two frames of the spinner that is shown while e.g. authenticating
+const SPINNER_INTERVAL: Duration = Duration::from_millis(150);
+
+/// How long to wait for an event before checking for pending background work (e.g. a reload of
+/// the configuration)
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the name of the session that is started is shown, before the login manager disappears
+const SESSION_CONFIRMATION_DURATION: Duration = Duration::from_millis(500);
+
+/// Starts a session for the submitted login form, which is [`start_session`] outside of tests
+type StartSession =
+    fn(&str, &str, &PostLoginEnvironment, &Hooks<'_>, &Config) -> Result<(), StartSessionError>;
 
-        *self = match self {
-            Normal => Normal,
-            Switcher => Normal,
-            Username => Switcher,
-            Password => Username,
+/// A source of terminal events for the login form
+///
+/// This allows the login form to be driven without an actual terminal.
+pub trait EventSource: Send {
+    /// Wait at most `timeout` for the next event. Returns `None` when no event arrived in time.
+    fn poll(&mut self, timeout: Duration) -> io::Result<Option<Event>>;
+
+    /// Whether no more events will ever arrive
+    fn is_exhausted(&self) -> bool {
+        false
+    }
+}
+
+/// The events coming from the actual terminal
+pub struct TerminalEvents;
+
+impl EventSource for TerminalEvents {
+    fn poll(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        if event::poll(timeout)? {
+            event::read().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Which of the fields were given a value before the user started typing
+#[derive(Clone, Copy)]
+struct CachedFields {
+    environment: bool,
+    username: bool,
+}
+
+/// Determine the field that is focused when lemurs starts
+fn initial_input_mode(focus_behaviour: &FocusBehaviour, cached: CachedFields) -> InputMode {
+    match focus_behaviour {
+        FocusBehaviour::FirstNonCached => {
+            if !cached.environment {
+                InputMode::Switcher
+            } else if !cached.username {
+                InputMode::Username
+            } else {
+                InputMode::Password
+            }
         }
+        FocusBehaviour::NoFocus => InputMode::Normal,
+        FocusBehaviour::Environment => InputMode::Switcher,
+        FocusBehaviour::Username => InputMode::Username,
+        FocusBehaviour::Password => InputMode::Password,
     }
 }
 
@@ -146,17 +297,65 @@ enum UIThreadRequest {
     DisableTui,
     EnableTui,
     StopDrawing,
+    /// Stop drawing, so that lemurs can replace itself
+    Reexec,
+}
+
+/// Why the login form stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormExit {
+    /// Lemurs should shut down, e.g. because the preview was closed
+    Quit,
+    /// Lemurs should replace itself with its binary, e.g. to pick up an upgraded version
+    Reexec,
 }
 
 #[derive(Clone)]
 struct Widgets {
-    power_menu: PowerMenuWidget,
+    /// The spacing between the widgets
+    layout: Arc<Mutex<LayoutConfig>>,
+
+    power_menu: Arc<Mutex<PowerMenuWidget>>,
     environment: Arc<Mutex<SwitcherWidget<PostLoginEnvironment>>>,
     username: Arc<Mutex<InputFieldWidget>>,
     password: Arc<Mutex<InputFieldWidget>>,
+    motd: Arc<Mutex<MotdWidget>>,
+    greeting: Arc<Mutex<GreetingWidget>>,
+    status_colors: Arc<Mutex<StatusMessageConfig>>,
+    /// The style of the version in the bottom-right corner. `None` hides the version.
+    version: Arc<Mutex<Option<Style>>>,
+    /// The style of the breadcrumb in the bottom-left corner. `None` hides the breadcrumb.
+    breadcrumb: Arc<Mutex<Option<Style>>>,
+    /// The overlay with the details of the selected environment, while it is shown
+    session_details: Arc<Mutex<Option<SessionDetailsWidget>>>,
+    /// The overlay to choose a variant of the selected environment, while it is shown
+    variant_picker: Arc<Mutex<Option<VariantPickerWidget>>>,
+    /// The on-screen keyboard, if it is enabled
+    keyboard: Arc<Mutex<Option<OnScreenKeyboardWidget>>>,
+
+    /// Draw without colors, for terminals that cannot show them (e.g. serial consoles)
+    basic_terminal: bool,
 }
 
 impl Widgets {
+    fn layout_guard(&self) -> MutexGuard<'_, LayoutConfig> {
+        match self.layout.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    fn power_menu_guard(&self) -> MutexGuard<PowerMenuWidget> {
+        match self.power_menu.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
     fn environment_guard(&self) -> MutexGuard<SwitcherWidget<PostLoginEnvironment>> {
         match self.environment.lock() {
             Ok(guard) => guard,
@@ -185,13 +384,93 @@ impl Widgets {
         }
     }
 
+    fn motd_guard(&self) -> MutexGuard<'_, MotdWidget> {
+        match self.motd.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn greeting_guard(&self) -> MutexGuard<'_, GreetingWidget> {
+        match self.greeting.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn status_colors_guard(&self) -> MutexGuard<'_, StatusMessageConfig> {
+        match self.status_colors.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn version_guard(&self) -> MutexGuard<'_, Option<Style>> {
+        match self.version.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn breadcrumb_guard(&self) -> MutexGuard<'_, Option<Style>> {
+        match self.breadcrumb.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn session_details_guard(&self) -> MutexGuard<'_, Option<SessionDetailsWidget>> {
+        match self.session_details.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn variant_picker_guard(&self) -> MutexGuard<'_, Option<VariantPickerWidget>> {
+        match self.variant_picker.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn keyboard_guard(&self) -> MutexGuard<'_, Option<OnScreenKeyboardWidget>> {
+        match self.keyboard.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
     fn get_environment(&self) -> Option<(String, PostLoginEnvironment)> {
         self.environment_guard()
             .selected()
             .map(|s| (s.title.clone(), s.content.clone()))
     }
-    fn environment_try_select(&self, title: &str) {
-        self.environment_guard().try_select(title);
+    fn environment_try_select(&self, title: &str) -> bool {
+        self.environment_guard().try_select(title)
     }
     fn get_username(&self) -> String {
         self.username_guard().get_content()
@@ -202,6 +481,9 @@ impl Widgets {
     fn get_password(&self) -> String {
         self.password_guard().get_content()
     }
+    fn set_password(&self, content: &str) {
+        self.password_guard().set_content(content)
+    }
     fn clear_password(&self) {
         self.password_guard().clear()
     }
@@ -215,16 +497,203 @@ pub struct LoginForm {
 
     widgets: Widgets,
 
+    input_mode: LoginFormInputMode,
+    status_message: LoginFormStatusMessage,
+
     /// The configuration for the app
     config: Config,
+    /// Where the configuration was loaded from. `None` means the default location.
+    config_path: Option<PathBuf>,
+    /// The environment that was chosen on the command line, which is selected instead of the
+    /// remembered one
+    initial_session: Option<String>,
+    /// Where the username and environments are remembered
+    cache: Arc<dyn InfoCache>,
+    /// The variants of an environment, which can be chosen in the variant picker
+    variants: fn(&PostLoginEnvironment) -> Vec<(String, PostLoginEnvironment)>,
+    start_session: StartSession,
+    /// The pipe to read credentials from, for automated tests
+    #[cfg(feature = "insecure-test-credentials")]
+    test_credentials_pipe: Option<String>,
+}
+
+fn environment_widget(config: &Config, preview: bool) -> SwitcherWidget<PostLoginEnvironment> {
+    let switcher_config = &config.environment_switcher;
+    let mut envs = crate::post_login::get_envs(
+        switcher_config.include_tty_shell,
+        &switcher_config.disabled_sessions,
+    );
+
+    // Development machines often have no environments installed in the lemurs folders. Show some
+    // samples, so the preview still shows what the environment switcher looks like.
+    if preview
+        && envs
+            .iter()
+            .all(|(_, env)| matches!(env, PostLoginEnvironment::Shell))
+    {
+        info!("No environments installed. Showing sample environments in the preview");
+        envs.splice(0..0, crate::post_login::get_sample_envs());
+    }
+
+    SwitcherWidget::new(
+        envs.into_iter()
+            .map(|(title, content)| SwitcherItem::new(title, content))
+            .collect(),
+        config.environment_switcher.clone(),
+    )
+}
+
+fn keyboard_widget(config: &Config) -> Option<OnScreenKeyboardWidget> {
+    config
+        .on_screen_keyboard
+        .enabled
+        .then(|| OnScreenKeyboardWidget::new(config.on_screen_keyboard.clone()))
+}
+
+/// The name of this machine
+fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    match nix::unistd::gethostname(&mut buffer) {
+        Ok(hostname) => Some(hostname.to_string_lossy().into_owned()),
+        Err(err) => {
+            warn!("Failed to get the hostname. Reason: {}", err);
+            None
+        }
+    }
+}
+
+/// The default environment for the machine with `hostname` and `seat`. The hostname takes
+/// precedence over the seat.
+fn default_environment<'a>(
+    config: &'a SwitcherConfig,
+    hostname: Option<&str>,
+    seat: &str,
+) -> &'a str {
+    let host_defaults = &config.host_default_environments;
+
+    hostname
+        .and_then(|hostname| host_defaults.get(hostname))
+        .or_else(|| host_defaults.get(seat))
+        .unwrap_or(&config.default_environment)
+}
+
+/// The fields of the login form in the order they are shown
+///
+/// The `environment_count` is the number of environments that can be selected.
+fn login_fields(config: &Config, environment_count: usize) -> Vec<InputMode> {
+    let use_fixed_username = config.username_field.use_fixed_username;
+    // There is nothing to choose, so the only environment is always selected
+    let hide_switcher = config.environment_switcher.hide_single && environment_count == 1;
+
+    let mut fields = Vec::new();
+    for field in config.field_order.iter().copied().map(InputMode::from) {
+        if fields.contains(&field) {
+            warn!(
+                "The field {:?} is listed multiple times in `field_order`",
+                field
+            );
+            continue;
+        }
+
+        if field == InputMode::Username && use_fixed_username {
+            continue;
+        }
+        if field == InputMode::Switcher && hide_switcher {
+            continue;
+        }
+
+        fields.push(field);
+    }
+
+    // It is impossible to login without these fields
+    if !use_fixed_username && !fields.contains(&InputMode::Username) {
+        warn!("The username field is missing from `field_order`. Adding it anyway");
+        fields.push(InputMode::Username);
+    }
+    if !fields.contains(&InputMode::Password) {
+        warn!("The password field is missing from `field_order`. Adding it anyway");
+        fields.push(InputMode::Password);
+    }
+
+    fields
+}
+
+/// The style of an input field. The borders are not drawn on basic terminals.
+fn input_field_style(config: &Config, style: &InputFieldStyle) -> InputFieldStyle {
+    let mut style = style.clone();
+    if config.terminal_mode.is_basic() {
+        style.show_border = false;
+    }
+
+    style
+}
+
+fn version_style(config: &Config) -> Option<Style> {
+    config
+        .show_version
+        .then(|| Style::default().fg(get_color(&config.version_color)))
+}
+
+fn breadcrumb_style(config: &Config) -> Option<Style> {
+    config
+        .show_breadcrumb
+        .then(|| Style::default().fg(get_color(&config.breadcrumb_color)))
+}
+
+/// The fields of the form with the focused one between brackets, followed by the keys that move
+/// away from it
+fn breadcrumb(input_mode: InputMode, fields: &[InputMode], style: Style) -> Spans<'static> {
+    let mut spans = Vec::new();
+    for (index, field) in [InputMode::Normal].iter().chain(fields).enumerate() {
+        if index > 0 {
+            spans.push(Span::styled(" > ", style));
+        }
+
+        if *field == input_mode {
+            spans.push(Span::styled(
+                format!("[{}]", field.label()),
+                style.add_modifier(Modifier::BOLD),
+            ));
+        } else {
+            spans.push(Span::styled(field.label(), style));
+        }
+    }
+
+    // Tab never leaves the last field and Shift-Tab never leaves the menu
+    let mut hints = Vec::new();
+    if fields.last() != Some(&input_mode) {
+        hints.push("Tab: next");
+    }
+    if input_mode != InputMode::Normal {
+        hints.push("Shift-Tab: back");
+    }
+    if !hints.is_empty() {
+        spans.push(Span::styled(format!("   {}", hints.join("  ")), style));
+    }
+
+    Spans::from(spans)
+}
+
+fn password_display_type(config: &Config) -> InputFieldDisplayType {
+    let replacement = config
+        .password_field
+        .content_replacement_character
+        .to_string();
+
+    if config.password_field.hide_length {
+        InputFieldDisplayType::Fixed(replacement, config.password_field.fixed_length)
+    } else {
+        InputFieldDisplayType::Replace(replacement)
+    }
 }
 
 impl LoginForm {
     fn set_cache(&self) {
         let env_remember = self.config.environment_switcher.remember;
+        let env_remember_per_user = self.config.environment_switcher.remember_per_user;
         let username_remember = self.config.username_field.remember;
 
-        if !env_remember && !username_remember {
+        if !env_remember && !env_remember_per_user && !username_remember {
             info!("Nothing to cache.");
             return;
         }
@@ -234,201 +703,953 @@ impl LoginForm {
         } else {
             None
         };
-        let username = self
-            .config
-            .username_field
-            .remember
+        let username_config = &self.config.username_field;
+        let username = (username_config.remember && !username_config.use_fixed_username)
             .then_some(self.widgets.get_username());
+        let user_env = if env_remember_per_user {
+            self.widgets
+                .get_environment()
+                .map(|(title, _)| (self.widgets.get_username(), title))
+        } else {
+            None
+        };
 
         info!("Setting cached information");
-        set_cache(selected_env.as_deref(), username.as_deref());
+        self.cache.set(
+            selected_env.as_deref(),
+            username.as_deref(),
+            user_env
+                .as_ref()
+                .map(|(username, env)| (username.as_str(), env.as_str())),
+        );
     }
 
-    fn load_cache(&self) {
+    fn load_cache(&self) -> CachedFields {
         let env_remember = self.config.environment_switcher.remember;
-        let username_remember = self.config.username_field.remember;
+        let username_remember =
+            self.config.username_field.remember && !self.config.username_field.use_fixed_username;
 
-        let cached = get_cached_information();
+        let cached = self.cache.get();
+
+        let mut cached_fields = CachedFields {
+            environment: false,
+            // A fixed username is always known beforehand
+            username: self.config.username_field.use_fixed_username,
+        };
 
         if username_remember {
             if let Some(username) = cached.username() {
                 info!("Loading username '{}' from cache", username);
                 self.widgets.set_username(username);
+                cached_fields.username = true;
             }
         }
-        if env_remember {
-            if let Some(env) = cached.environment() {
+        match cached.environment() {
+            Some(env) if env_remember => {
                 info!("Loading environment '{}' from cache", env);
-                self.widgets.environment_try_select(env);
+                cached_fields.environment = self.select_cached_environment(env);
             }
+            _ => self.select_default_environment(),
+        }
+
+        if self.load_user_environment() {
+            cached_fields.environment = true;
         }
+
+        if let Some(session) = &self.initial_session {
+            info!("Selecting the session '{}' from the command line", session);
+            self.widgets.environment_try_select(session);
+            cached_fields.environment = true;
+        }
+
+        cached_fields
     }
 
-    pub fn new(config: Config, preview: bool) -> LoginForm {
-        LoginForm {
-            preview,
-            widgets: Widgets {
-                power_menu: PowerMenuWidget::new(config.power_controls.clone()),
-                environment: Arc::new(Mutex::new(SwitcherWidget::new(
-                    crate::post_login::get_envs(config.environment_switcher.include_tty_shell)
-                        .into_iter()
-                        .map(|(title, content)| SwitcherItem::new(title, content))
-                        .collect(),
-                    config.environment_switcher.clone(),
-                ))),
-                username: Arc::new(Mutex::new(InputFieldWidget::new(
-                    InputFieldDisplayType::Echo,
-                    config.username_field.style.clone(),
-                    String::default(),
-                ))),
-                password: Arc::new(Mutex::new(InputFieldWidget::new(
-                    InputFieldDisplayType::Replace(
-                        config
-                            .password_field
-                            .content_replacement_character
-                            .to_string(),
-                    ),
-                    config.password_field.style.clone(),
-                    String::default(),
-                ))),
-            },
-            config,
+    /// Select the environment that was last used by the user within the username field
+    ///
+    /// Returns whether an environment was found for the user.
+    fn load_user_environment(&self) -> bool {
+        // The session from the command line always wins
+        if !self.config.environment_switcher.remember_per_user || self.initial_session.is_some() {
+            return false;
         }
+
+        let username = self.widgets.get_username();
+        if username.is_empty() {
+            return false;
+        }
+
+        let cached = self.cache.get();
+        let Some(env) = cached.user_environment(&username) else {
+            return false;
+        };
+
+        info!(
+            "Loading environment '{}' for user '{}' from cache",
+            env, username
+        );
+        self.select_cached_environment(env)
     }
 
-    pub fn run(self, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
-        self.load_cache();
-        let input_mode = LoginFormInputMode::new(match self.config.focus_behaviour {
-            FocusBehaviour::FirstNonCached => match (
-                self.config.username_field.remember && !self.widgets.get_username().is_empty(),
-                self.config.environment_switcher.remember
-                    && self
-                        .widgets
-                        .get_environment()
-                        .map(|(title, _)| !title.is_empty())
-                        .unwrap_or(false),
-            ) {
-                (true, true) => InputMode::Password,
-                (true, _) => InputMode::Username,
-                _ => InputMode::Switcher,
-            },
-            FocusBehaviour::NoFocus => InputMode::Normal,
-            FocusBehaviour::Environment => InputMode::Switcher,
-            FocusBehaviour::Username => InputMode::Username,
-            FocusBehaviour::Password => InputMode::Password,
-        });
-        let status_message = LoginFormStatusMessage::new();
+    /// Select a remembered environment. When it is no longer installed, the configured
+    /// `missing_environment` policy is followed.
+    ///
+    /// Returns whether an environment was selected, so the user does not have to choose one.
+    fn select_cached_environment(&self, env: &str) -> bool {
+        if self.widgets.environment_try_select(env) {
+            return true;
+        }
 
-        let power_menu = self.widgets.power_menu.clone();
-        let environment = self.widgets.environment.clone();
-        let username = self.widgets.username.clone();
-        let password = self.widgets.password.clone();
+        let switcher_config = &self.config.environment_switcher;
+        match switcher_config.missing_environment {
+            MissingEnvironment::First => true,
+            MissingEnvironment::Default => {
+                self.select_default_environment();
+                true
+            }
+            MissingEnvironment::Notice => {
+                self.status_message
+                    .set(InfoStatusMessage::MissingEnvironment(env.to_string()));
 
-        match terminal.draw(|f| {
-            let layout = Chunks::new(f);
-            login_form_render(
-                f,
-                layout,
-                power_menu.clone(),
-                environment.clone(),
-                username.clone(),
-                password.clone(),
-                input_mode.get(),
-                status_message.get(),
-            );
-        }) {
-            Ok(_) => {}
-            Err(err) => {
-                error!("Failed to draw. Reason: {}", err);
-                std::process::exit(1);
+                false
             }
         }
+    }
 
-        let event_input_mode = input_mode.clone();
-        let event_status_message = status_message.clone();
-
-        let (req_send_channel, req_recv_channel) = channel();
-        std::thread::spawn(move || {
-            let input_mode = event_input_mode;
-            let status_message = event_status_message;
+    /// Select the default environment of this machine, if there is one
+    fn select_default_environment(&self) {
+        let seat = std::env::var("XDG_SEAT").unwrap_or_else(|_| "seat0".to_string());
+        let default_env = default_environment(
+            &self.config.environment_switcher,
+            hostname().as_deref(),
+            &seat,
+        );
 
-            let send_ui_request = |request: UIThreadRequest| match req_send_channel.send(request) {
-                Ok(_) => {}
-                Err(err) => warn!("Failed to send UI request. Reason: {}", err),
-            };
+        if !default_env.is_empty() {
+            info!("Selecting the default environment '{}'", default_env);
+            self.widgets.environment_try_select(default_env);
+        }
+    }
 
-            let pre_auth = || {
-                self.widgets.clear_password();
+    /// Clear all the fields and move back to the first field
+    fn reset_form(&self) {
+        info!("Too many failed login attempts. Resetting the login form");
 
-                status_message.set(InfoStatusMessage::Authenticating);
-                send_ui_request(UIThreadRequest::Redraw);
-            };
-            let pre_environment = || {
-                // Remember username and environment for next time
-                self.set_cache();
+        if !self.config.username_field.use_fixed_username {
+            self.widgets.username_guard().clear();
+        }
+        self.widgets.clear_password();
 
-                status_message.set(InfoStatusMessage::LoggingIn);
-                send_ui_request(UIThreadRequest::Redraw);
+        if let Some(first_field) = self.input_mode.fields.first() {
+            self.input_mode.set(*first_field);
+        }
+    }
 
-                // Disable the rendering of the login manager
-                send_ui_request(UIThreadRequest::DisableTui);
-            };
-            let pre_return = || {
-                // Enable the rendering of the login manager
-                send_ui_request(UIThreadRequest::EnableTui);
+    /// Select the safe session after the session `failed` failed to start too many times in a row
+    fn select_safe_session(&self, failed: &str) {
+        let safe = &self.config.failure_fallback.safe_session;
+        if safe.is_empty() || safe == failed {
+            return;
+        }
 
-                status_message.clear();
-                send_ui_request(UIThreadRequest::Redraw);
-            };
+        warn!("The session '{failed}' keeps failing to start. Selecting the safe session '{safe}'");
+        if self.widgets.environment_try_select(safe) {
+            self.status_message
+                .set(ErrorStatusMessage::SafeSessionSelected {
+                    failed: failed.to_string(),
+                    safe: safe.clone(),
+                });
+        }
+    }
 
-            let hooks = Hooks {
-                pre_validate: None,
-                pre_auth: Some(&pre_auth),
-                pre_environment: Some(&pre_environment),
-                pre_wait: None,
-                pre_return: Some(&pre_return),
+    /// Run the idle command after nobody used the login screen for the `idle_timeout`
+    fn idle_power_action(&self) {
+        let power_config = &self.config.power_controls;
+        let command = if power_config.idle_cmd.is_empty() {
+            &power_config.shutdown_cmd
+        } else {
+            &power_config.idle_cmd
+        };
+
+        if self.preview {
+            info!(
+                "Nobody logged in for {} seconds. Not running '{}' in the preview",
+                power_config.idle_timeout, command
+            );
+            return;
+        }
+
+        info!(
+            "Nobody logged in for {} seconds. Running '{}'",
+            power_config.idle_timeout, command
+        );
+        match Command::new("/bin/sh").arg("-c").arg(command).status() {
+            Ok(status) if !status.success() => {
+                warn!("The idle command failed with {}", status)
+            }
+            Ok(_) => {}
+            Err(err) => error!("Failed to run the idle command. Reason: {}", err),
+        }
+    }
+
+    /// Take the configured action after logging in failed too many times in a row
+    fn failure_fallback(&self, send_ui_request: &impl Fn(UIThreadRequest)) {
+        let fallback = &self.config.failure_fallback;
+        warn!(
+            "Logging in failed {} times in a row. Taking the fallback action",
+            fallback.max_failures
+        );
+
+        match fallback.action {
+            FailureAction::Instructions => {
+                self.status_message.set(ErrorStatusMessage::TooManyFailures(
+                    fallback.instructions.clone(),
+                ));
+            }
+            FailureAction::RescueShell => {
+                info!("Starting the rescue shell '{}'", fallback.rescue_cmd);
+                send_ui_request(UIThreadRequest::DisableTui);
+
+                match Command::new("/bin/sh")
+                    .arg("-c")
+                    .arg(&fallback.rescue_cmd)
+                    .status()
+                {
+                    Ok(status) if !status.success() => {
+                        warn!("The rescue shell exited with a failure. Status: {status}")
+                    }
+                    Ok(_) => info!("The rescue shell exited"),
+                    Err(err) => error!("Failed to start the rescue shell. Reason: {err}"),
+                }
+
+                send_ui_request(UIThreadRequest::EnableTui);
+                self.status_message.clear();
+            }
+        }
+
+        send_ui_request(UIThreadRequest::Redraw);
+    }
+
+    /// Pass a key to the username field. Moves to the next field when the username is complete
+    /// according to the configuration, e.g. for badge scanners that type a username followed by a
+    /// terminator.
+    fn username_key_press(&self, key: KeyEvent) -> Option<ErrorStatusMessage> {
+        let field_config = &self.config.username_field;
+        let is_typed =
+            matches!(key.code, KeyCode::Char(_)) && !key.modifiers.contains(KeyModifiers::CONTROL);
+
+        if let KeyCode::Char(c) = key.code {
+            if is_typed && field_config.advance_characters.contains(c) {
+                self.input_mode.next();
+                return None;
+            }
+        }
+
+        let mut username = self.widgets.username_guard();
+        let status_message = username.key_press(key);
+
+        let advance_length = usize::from(field_config.advance_length);
+        if advance_length != 0
+            && is_typed
+            && username.get_content().chars().count() >= advance_length
+        {
+            drop(username);
+            self.input_mode.next();
+        }
+
+        status_message
+    }
+
+    /// The key that is pressed by clicking on the on-screen keyboard
+    fn keyboard_click(&self, mouse: MouseEvent) -> Option<KeyEvent> {
+        if mouse.kind != MouseEventKind::Down(MouseButton::Left) {
+            return None;
+        }
+
+        let code = self
+            .widgets
+            .keyboard_guard()
+            .as_mut()?
+            .click(mouse.column, mouse.row)?;
+        Some(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    /// Prepare the form for a login that was triggered from outside, e.g. by inserting a smartcard
+    fn trigger_login(&self, trigger: &LoginTrigger) {
+        info!("Login triggered for '{}'", trigger.username);
+
+        if self.config.username_field.use_fixed_username {
+            warn!("Ignoring the username of the login trigger, because a fixed username is used");
+        } else {
+            self.widgets.set_username(&trigger.username);
+        }
+        match &trigger.password {
+            Some(password) => self.widgets.set_password(password),
+            None => self.widgets.clear_password(),
+        }
+        if let Some(session) = &trigger.session {
+            self.widgets.environment_try_select(session);
+        }
+        *self.widgets.session_details_guard() = None;
+        *self.widgets.variant_picker_guard() = None;
+
+        if let Some(last_field) = self.input_mode.fields.last() {
+            self.input_mode.set(*last_field);
+        }
+    }
+
+    /// Show the overlay with the details of the selected environment
+    fn show_session_details(&self) {
+        let Some((title, environment)) = self.widgets.get_environment() else {
+            info!("No environment is selected. Not showing the session details");
+            return;
+        };
+
+        *self.widgets.session_details_guard() = Some(SessionDetailsWidget::new(
+            title,
+            environment.details(&self.config),
+        ));
+    }
+
+    /// Show the overlay to choose a variant of the selected environment, if it has several
+    fn show_variant_picker(&self) {
+        let Some((title, environment)) = self.widgets.get_environment() else {
+            return;
+        };
+
+        let variants = (self.variants)(&environment);
+        if variants.len() < 2 {
+            return;
+        }
+
+        *self.widgets.variant_picker_guard() =
+            Some(VariantPickerWidget::new(title, variants, &environment));
+    }
+
+    /// Handle a key while the variant picker is shown. The chosen variant replaces the selected
+    /// environment, so that it keeps its title.
+    fn variant_picker_key_press(&self, code: KeyCode) {
+        let mut variant_picker = self.widgets.variant_picker_guard();
+        let Some(picker) = variant_picker.as_mut() else {
+            return;
+        };
+
+        match code {
+            KeyCode::Up | KeyCode::Left => picker.prev(),
+            KeyCode::Down | KeyCode::Right | KeyCode::Tab => picker.next(),
+            KeyCode::Enter => {
+                if let Some(variant) = picker.selected() {
+                    info!("Choosing the variant {:?}", variant);
+                    self.widgets
+                        .environment_guard()
+                        .set_selected_content(variant.clone());
+                }
+                *variant_picker = None;
+            }
+            KeyCode::Esc => *variant_picker = None,
+            _ => {}
+        }
+    }
+
+    /// Switch to the console tty, while lemurs keeps running on its own tty
+    fn switch_console(&self) -> Option<ErrorStatusMessage> {
+        let tty = self.config.console_switch_tty;
+
+        if self.preview {
+            info!("Pressed the console switch key in preview mode. Not switching to tty {tty}");
+            return None;
+        }
+
+        if Some(tty) == self.config.tty.number() {
+            warn!("The console switch tty is the tty of lemurs. Not switching to tty {tty}");
+            return None;
+        }
+
+        info!("Switching to the console on tty {tty}");
+        match unsafe { crate::chvt::chvt(tty.into()) } {
+            Ok(()) => None,
+            Err(err) => {
+                error!("Failed to switch to the console on tty {tty}. Reason: {err}");
+                Some(ErrorStatusMessage::FailedConsoleSwitch)
+            }
+        }
+    }
+
+    /// Load the configuration file again and apply it to the login form
+    ///
+    /// Options that cannot be changed while lemurs is running are kept at their current value.
+    fn reload_config(&mut self) {
+        info!("Reloading configuration");
+
+        let mut config = match reload_configuration(self.config_path.as_deref()) {
+            Ok(config) => config,
+            Err(err) => {
+                warn!(
+                    "Failed to reload configuration. Keeping the current configuration. Reason: {}",
+                    err
+                );
+                return;
+            }
+        };
+
+        // "auto" always refers to the tty that lemurs is already on, which was already determined
+        if config.tty != self.config.tty {
+            if config.tty != Tty::Auto {
+                warn!(
+                    "Changing the tty requires a restart of lemurs. Staying on tty {}",
+                    self.config.tty
+                );
+            }
+            config.tty = self.config.tty;
+        }
+
+        let username_config = &self.config.username_field;
+        if config.username_field.use_fixed_username != username_config.use_fixed_username
+            || config.username_field.fixed_username != username_config.fixed_username
+        {
+            warn!("Changing the fixed username requires a restart of lemurs. Keeping the current username");
+            config.username_field.use_fixed_username = username_config.use_fixed_username;
+            config.username_field.fixed_username = username_config.fixed_username.clone();
+        }
+
+        if config.terminal_mode != self.config.terminal_mode {
+            warn!(
+                "Changing the terminal mode requires a restart of lemurs. Keeping the current mode"
+            );
+            config.terminal_mode = self.config.terminal_mode;
+        }
+
+        if config.on_screen_keyboard.enabled != self.config.on_screen_keyboard.enabled {
+            warn!("Enabling or disabling the on-screen keyboard requires a restart of lemurs. Keeping the current setting");
+            config.on_screen_keyboard.enabled = self.config.on_screen_keyboard.enabled;
+        }
+
+        if config.motd.command != self.config.motd.command
+            || config.motd.command_interval != self.config.motd.command_interval
+        {
+            warn!("Changing the message of the day command requires a restart of lemurs. Keeping the current command");
+            config.motd.command = self.config.motd.command.clone();
+            config.motd.command_interval = self.config.motd.command_interval;
+        }
+
+        if config.field_order != self.config.field_order {
+            warn!(
+                "Changing the field order requires a restart of lemurs. Keeping the current order"
+            );
+            config.field_order = self.config.field_order.clone();
+        }
+
+        // Keep the selected environment if it still exists
+        let selected_env = self.widgets.get_environment().map(|(title, _)| title);
+        *self.widgets.environment_guard() = environment_widget(&config, self.preview);
+        *self.widgets.variant_picker_guard() = None;
+        if let Some(title) = selected_env {
+            self.widgets.environment_try_select(&title);
+        }
+
+        *self.widgets.layout_guard() = config.layout.clone();
+        *self.widgets.power_menu_guard() = PowerMenuWidget::new(config.power_controls.clone());
+        if config.motd.command.is_empty() {
+            *self.widgets.motd_guard() = MotdWidget::new(config.motd.clone());
+        } else {
+            // The output of the command stays until it runs again
+            self.widgets.motd_guard().set_config(config.motd.clone());
+        }
+        *self.widgets.greeting_guard() = GreetingWidget::new(config.greeting.clone());
+        *self.widgets.status_colors_guard() = config.status_message.clone();
+        *self.widgets.version_guard() = version_style(&config);
+        *self.widgets.breadcrumb_guard() = breadcrumb_style(&config);
+        *self.widgets.keyboard_guard() = keyboard_widget(&config);
+        self.widgets
+            .username_guard()
+            .set_style(input_field_style(&config, &config.username_field.style));
+
+        let mut password = self.widgets.password_guard();
+        password.set_style(input_field_style(&config, &config.password_field.style));
+        password.set_display_type(password_display_type(&config));
+        drop(password);
+
+        self.config = config;
+    }
+
+    pub fn new(config: Config, preview: bool, config_path: Option<PathBuf>) -> LoginForm {
+        let preset_username = if config.username_field.use_fixed_username {
+            config.username_field.fixed_username.clone()
+        } else {
+            String::default()
+        };
+
+        let environment = environment_widget(&config, preview);
+        let fields = login_fields(&config, environment.item_count());
+
+        LoginForm {
+            preview,
+            widgets: Widgets {
+                layout: Arc::new(Mutex::new(config.layout.clone())),
+                power_menu: Arc::new(Mutex::new(PowerMenuWidget::new(
+                    config.power_controls.clone(),
+                ))),
+                environment: Arc::new(Mutex::new(environment)),
+                username: Arc::new(Mutex::new(InputFieldWidget::new(
+                    InputFieldDisplayType::Echo,
+                    input_field_style(&config, &config.username_field.style),
+                    preset_username,
+                ))),
+                password: Arc::new(Mutex::new(InputFieldWidget::new(
+                    password_display_type(&config),
+                    input_field_style(&config, &config.password_field.style),
+                    String::default(),
+                ))),
+                motd: Arc::new(Mutex::new(MotdWidget::new(config.motd.clone()))),
+                greeting: Arc::new(Mutex::new(GreetingWidget::new(config.greeting.clone()))),
+                status_colors: Arc::new(Mutex::new(config.status_message.clone())),
+                version: Arc::new(Mutex::new(version_style(&config))),
+                breadcrumb: Arc::new(Mutex::new(breadcrumb_style(&config))),
+                session_details: Arc::new(Mutex::new(None)),
+                variant_picker: Arc::new(Mutex::new(None)),
+                keyboard: Arc::new(Mutex::new(keyboard_widget(&config))),
+                basic_terminal: config.terminal_mode.is_basic(),
+            },
+            input_mode: LoginFormInputMode::new(fields),
+            status_message: LoginFormStatusMessage::new(),
+            config,
+            config_path,
+            initial_session: None,
+            cache: Arc::new(FileCache),
+            variants: PostLoginEnvironment::variants,
+            start_session,
+            #[cfg(feature = "insecure-test-credentials")]
+            test_credentials_pipe: None,
+        }
+    }
+
+    /// Select `session` instead of the remembered or default environment. Sessions that do not
+    /// exist are ignored.
+    pub fn with_initial_session(mut self, session: Option<String>) -> Self {
+        self.initial_session = session.filter(|session| {
+            let exists = self.widgets.environment_try_select(session);
+            if !exists {
+                warn!("The session '{session}' does not exist. Using the normal selection");
+            }
+
+            exists
+        });
+
+        self
+    }
+
+    /// Login with the credentials that are written to `pipe`. See [`crate::test_credentials`].
+    #[cfg(feature = "insecure-test-credentials")]
+    pub fn with_test_credentials(mut self, pipe: Option<String>) -> Self {
+        self.test_credentials_pipe = pipe;
+        self
+    }
+
+    /// Remember the username and environments in `cache` instead of the cache file
+    #[cfg(test)]
+    fn with_cache(mut self, cache: Arc<dyn InfoCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Find the variants of environments with `variants` instead of in the environment folders
+    #[cfg(test)]
+    fn with_variants(
+        mut self,
+        variants: fn(&PostLoginEnvironment) -> Vec<(String, PostLoginEnvironment)>,
+    ) -> Self {
+        self.variants = variants;
+        self
+    }
+
+    /// Log in with `start_session` instead of with PAM
+    #[cfg(test)]
+    fn with_start_session(mut self, start_session: StartSession) -> Self {
+        self.start_session = start_session;
+        self
+    }
+
+    pub fn run<B: Backend>(
+        mut self,
+        terminal: &mut Terminal<B>,
+        mut events: impl EventSource + 'static,
+        use_alternate_screen: bool,
+    ) -> io::Result<FormExit> {
+        let cached_fields = self.load_cache();
+        let input_mode = self.input_mode.clone();
+        let status_message = self.status_message.clone();
+        let fields = input_mode.fields.clone();
+        input_mode.set(initial_input_mode(
+            &self.config.focus_behaviour,
+            cached_fields,
+        ));
+
+        let widgets = self.widgets.clone();
+
+        let screen_clearing = self.config.clear_on_session_switch;
+        let cursor_style =
+            SetCursorStyle::from_config(self.config.cursor_shape, self.config.cursor_blink);
+        let _cursor_style_guard = CursorStyleGuard::apply(cursor_style)?;
+        // Touches on the on-screen keyboard arrive as mouse events
+        let capture_mouse = widgets.keyboard_guard().is_some();
+        if capture_mouse {
+            execute!(io::stdout(), EnableMouseCapture)?;
+        }
+
+        // The hour that the greeting was last drawn for
+        let mut greeting_hour = widgets.greeting_guard().hour();
+        match terminal.draw(|f| {
+            let layout = Chunks::new(f, &widgets.layout_guard(), &fields);
+            login_form_render(
+                f,
+                layout,
+                &widgets,
+                input_mode.get(),
+                status_message.get(),
+                &fields,
+                0,
+            );
+        }) {
+            Ok(_) => {}
+            Err(err) => {
+                error!("Failed to draw. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+
+        let event_input_mode = input_mode.clone();
+        let event_status_message = status_message.clone();
+
+        let mut login_triggers = Vec::new();
+        if !self.config.login_trigger_pipe.is_empty() {
+            login_triggers.extend(crate::login_trigger::listen(
+                &self.config.login_trigger_pipe,
+            ));
+        }
+        #[cfg(feature = "insecure-test-credentials")]
+        if let Some(pipe) = &self.test_credentials_pipe {
+            login_triggers.extend(crate::test_credentials::listen(pipe));
+        }
+
+        let motd_updates = motd::watch_command(&self.config.motd);
+
+        let (req_send_channel, req_recv_channel) = channel();
+        std::thread::spawn(move || {
+            let input_mode = event_input_mode;
+            let status_message = event_status_message;
+
+            let send_ui_request = |request: UIThreadRequest| match req_send_channel.send(request) {
+                Ok(_) => {}
+                Err(err) => warn!("Failed to send UI request. Reason: {}", err),
             };
 
+            // The number of times in a row that logging in failed
+            let mut failures = 0;
+            // The number of times in a row that the authentication failed
+            let mut failed_attempts = 0;
+            // The user that logged out last, who can login again without a password for a while
+            let mut remembered_login: Option<RememberedLogin> = None;
+            // The number of times in a row that each session failed to start
+            let mut session_failures: HashMap<String, u32> = HashMap::new();
+            // When the last key was pressed or the screen was touched
+            let mut last_input = Instant::now();
+
             loop {
-                if let Ok(Event::Key(key)) = event::read() {
+                if events.is_exhausted() {
+                    info!("No more events will arrive. Stopping the application");
+                    send_ui_request(UIThreadRequest::StopDrawing);
+                    break;
+                }
+
+                if signals::take_reload_request() {
+                    self.reload_config();
+                    send_ui_request(UIThreadRequest::Redraw);
+                }
+
+                if signals::take_hangup_request() {
+                    match self.config.sighup_action {
+                        HangupAction::Reload => {
+                            self.reload_config();
+                            send_ui_request(UIThreadRequest::Redraw);
+                        }
+                        HangupAction::Reexec => {
+                            info!("Received SIGHUP without a running session. Restarting lemurs");
+                            send_ui_request(UIThreadRequest::Reexec);
+                            break;
+                        }
+                    }
+                }
+
+                let motd_update = motd_updates
+                    .as_ref()
+                    .and_then(|updates| updates.try_recv().ok());
+                if let Some(lines) = motd_update {
+                    self.widgets.motd_guard().set_lines(lines);
+                    send_ui_request(UIThreadRequest::Redraw);
+                }
+
+                let idle_timeout = self.config.power_controls.idle_timeout;
+                if idle_timeout > 0 && last_input.elapsed() >= Duration::from_secs(idle_timeout) {
+                    self.idle_power_action();
+                    last_input = Instant::now();
+                }
+
+                let login_trigger = login_triggers
+                    .iter()
+                    .find_map(|triggers| triggers.try_recv().ok());
+                let key = if let Some(trigger) = login_trigger {
+                    self.trigger_login(&trigger);
+
+                    // Take the same path as pressing Enter in the last field
+                    Some(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+                } else {
+                    match events.poll(EVENT_POLL_INTERVAL) {
+                        Ok(Some(Event::Key(key))) => {
+                            last_input = Instant::now();
+                            Some(key)
+                        }
+                        Ok(Some(Event::Mouse(mouse))) => {
+                            last_input = Instant::now();
+                            self.keyboard_click(mouse)
+                        }
+                        Ok(Some(_)) => None,
+                        // Nothing happened, so there is nothing to redraw
+                        Ok(None) => continue,
+                        // A signal arrived while waiting (e.g. SIGUSR1 for a reload). `poll` is not
+                        // restarted by `SA_RESTART`, so this is not a failure. Handle the signal and
+                        // wait again.
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(err) => {
+                            warn!("Failed to read event. Reason: {}", err);
+                            None
+                        }
+                    }
+                };
+
+                if let Some(key) = key {
+                    let previous_input_mode = input_mode.get();
+
                     match (key.code, input_mode.get()) {
-                        (KeyCode::Enter, InputMode::Password) => {
+                        // Ctrl-C quits the preview from anywhere. It should never be possible to
+                        // escape the actual login screen, so it does nothing outside of the preview.
+                        (KeyCode::Char('c'), _) if key.modifiers == KeyModifiers::CONTROL => {
+                            if self.preview {
+                                info!("Pressed Ctrl-C in preview mode to exit the application");
+                                send_ui_request(UIThreadRequest::StopDrawing);
+                                break;
+                            }
+                        }
+                        // The details overlay captures all keys until it is closed
+                        (k, _) if self.widgets.session_details_guard().is_some() => {
+                            if k == KeyCode::Esc || k == get_key(&self.config.session_details_key) {
+                                *self.widgets.session_details_guard() = None;
+                            }
+                        }
+                        (k, _) if self.widgets.variant_picker_guard().is_some() => {
+                            self.variant_picker_key_press(k);
+                        }
+                        (k, _) if k == get_key(&self.config.session_details_key) => {
+                            self.show_session_details();
+                        }
+                        (k, _)
+                            if self.config.allow_console_switch
+                                && k == get_key(&self.config.console_switch_key) =>
+                        {
+                            if let Some(status_msg) = self.switch_console() {
+                                status_message.set(status_msg);
+                            }
+                        }
+                        (KeyCode::Enter, _) if input_mode.is_last_field() => {
                             if self.preview {
                                 // This is only for demonstration purposes
                                 status_message.set(InfoStatusMessage::Authenticating);
                                 send_ui_request(UIThreadRequest::Redraw);
                                 std::thread::sleep(Duration::from_secs(2));
 
-                                status_message.set(InfoStatusMessage::LoggingIn);
+                                let logging_in = match self.widgets.get_environment() {
+                                    Some((title, _)) => InfoStatusMessage::StartingSession(title),
+                                    None => InfoStatusMessage::LoggingIn,
+                                };
+                                status_message.set(logging_in);
                                 send_ui_request(UIThreadRequest::Redraw);
                                 std::thread::sleep(Duration::from_secs(2));
 
                                 status_message.clear();
                                 send_ui_request(UIThreadRequest::Redraw);
                             } else {
-                                let environment =
-                                    self.widgets.get_environment().map(|(_, content)| content);
+                                let selected_environment = self.widgets.get_environment();
                                 let username = self.widgets.get_username();
                                 let password = self.widgets.get_password();
-                                let config = self.config.clone();
+                                let mut config = self.config.clone();
+
+                                // A remembered login is only used once, and a typed password
+                                // always takes precedence. PAM decides whether the login is still
+                                // allowed without a password.
+                                let remembered = remembered_login.take();
+                                if password.is_empty()
+                                    && remembered.is_some_and(|login| login.allows(&username))
+                                {
+                                    info!("Logging in '{}' again without a password", username);
+                                    config.pam_service = config.fast_relogin_pam_service.clone();
+                                }
+
+                                let clear_on_failure = config.password_field.clear_on_failure;
+                                let pre_auth = || {
+                                    if clear_on_failure {
+                                        self.widgets.clear_password();
+                                    }
+
+                                    status_message.set(InfoStatusMessage::Authenticating);
+                                    send_ui_request(UIThreadRequest::Redraw);
+                                };
+                                // Shells need the terminal, but graphical sessions take over the
+                                // screen by themselves once they are started.
+                                let session_title = selected_environment
+                                    .as_ref()
+                                    .map(|(title, _)| title.clone());
+                                let starting_session = selected_environment
+                                    .as_ref()
+                                    .filter(|(_, env)| {
+                                        config.show_starting_screen
+                                            && !matches!(env, PostLoginEnvironment::Shell)
+                                    })
+                                    .map(|(title, _)| title.clone());
+
+                                let pre_environment = || {
+                                    // The password is no longer needed, even if it would be kept
+                                    // after a failure
+                                    self.widgets.clear_password();
+
+                                    // Remember username and environment for next time
+                                    self.set_cache();
+
+                                    if let Some(title) = &starting_session {
+                                        status_message
+                                            .set(InfoStatusMessage::StartingSession(title.clone()));
+                                        send_ui_request(UIThreadRequest::Redraw);
+                                    } else {
+                                        // Briefly confirm which session is started, since the
+                                        // login manager disappears directly after
+                                        match &session_title {
+                                            Some(title) => {
+                                                status_message.set(
+                                                    InfoStatusMessage::StartingSession(
+                                                        title.clone(),
+                                                    ),
+                                                );
+                                                send_ui_request(UIThreadRequest::Redraw);
+                                                std::thread::sleep(SESSION_CONFIRMATION_DURATION);
+                                            }
+                                            None => {
+                                                status_message.set(InfoStatusMessage::LoggingIn);
+                                                send_ui_request(UIThreadRequest::Redraw);
+                                            }
+                                        }
+
+                                        // Disable the rendering of the login manager
+                                        send_ui_request(UIThreadRequest::DisableTui);
+                                    }
+                                };
+                                let pre_wait = || {
+                                    // The session is running now, so it is safe to stop rendering
+                                    if starting_session.is_some() {
+                                        send_ui_request(UIThreadRequest::DisableTui);
+                                    }
+                                };
+                                let pre_return = || {
+                                    // Enable the rendering of the login manager
+                                    send_ui_request(UIThreadRequest::EnableTui);
+
+                                    status_message.clear();
+                                    send_ui_request(UIThreadRequest::Redraw);
+                                };
+
+                                let hooks = Hooks {
+                                    pre_validate: None,
+                                    pre_auth: Some(&pre_auth),
+                                    pre_environment: Some(&pre_environment),
+                                    pre_wait: Some(&pre_wait),
+                                    pre_return: Some(&pre_return),
+                                };
 
-                                let Some(post_login_env) = environment else {
+                                let Some((_, post_login_env)) = selected_environment else {
                                     status_message.set(ErrorStatusMessage::NoGraphicalEnvironment);
                                     send_ui_request(UIThreadRequest::Redraw);
-                                    continue
+                                    continue;
                                 };
 
-                                match start_session(
-                                    &username,
-                                    &password,
-                                    &post_login_env,
-                                    &hooks,
-                                    &config,
-                                ) {
-                                    Ok(()) => {}
+                                // A bug while logging in should not take down the login screen
+                                let result = match panic::catch_unwind(AssertUnwindSafe(|| {
+                                    (self.start_session)(
+                                        &username,
+                                        &password,
+                                        &post_login_env,
+                                        &hooks,
+                                        &config,
+                                    )
+                                })) {
+                                    Ok(result) => result,
+                                    Err(payload) => {
+                                        error!(
+                                            "Logging in panicked. Reason: {}",
+                                            panic_message(payload.as_ref())
+                                        );
+                                        send_ui_request(UIThreadRequest::EnableTui);
+                                        status_message.set(ErrorStatusMessage::UnexpectedFailure);
+                                        send_ui_request(UIThreadRequest::Redraw);
+                                        continue;
+                                    }
+                                };
+                                let is_failure = matches!(
+                                    result,
+                                    Err(StartSessionError::AuthenticationError(_)
+                                        | StartSessionError::EnvironmentStartError(_))
+                                );
+                                let is_started = result.is_ok();
+                                let is_session_failure = matches!(
+                                    result,
+                                    Err(StartSessionError::EnvironmentStartError(_))
+                                );
+
+                                match result {
+                                    Ok(()) => {
+                                        failures = 0;
+                                        failed_attempts = 0;
+
+                                        let window = config.fast_relogin_minutes;
+                                        if window > 0 {
+                                            remembered_login = Some(RememberedLogin::new(
+                                                &username,
+                                                Duration::from_secs(window * 60),
+                                            ));
+                                        }
+                                    }
                                     Err(StartSessionError::AuthenticationError(err)) => {
                                         status_message
                                             .set(ErrorStatusMessage::AuthenticationError(err));
+
+                                        failed_attempts += 1;
+                                        let reset_after = config.reset_after_attempts;
+                                        if reset_after > 0 && failed_attempts >= reset_after {
+                                            failed_attempts = 0;
+                                            self.reset_form();
+                                        }
+
+                                        send_ui_request(UIThreadRequest::Redraw);
+                                    }
+                                    Err(StartSessionError::AlreadyLoggedIn) => {
+                                        status_message.set(ErrorStatusMessage::AlreadyLoggedIn);
+                                        send_ui_request(UIThreadRequest::Redraw);
+                                    }
+                                    Err(StartSessionError::LoginHookFailed) => {
+                                        status_message.set(ErrorStatusMessage::LoginRefused);
+                                        send_ui_request(UIThreadRequest::Redraw);
+                                    }
+                                    Err(
+                                        StartSessionError::NoLogin(message)
+                                        | StartSessionError::Maintenance(message),
+                                    ) => {
+                                        status_message.set(ErrorStatusMessage::NoLogin(message));
                                         send_ui_request(UIThreadRequest::Redraw);
                                     }
                                     Err(StartSessionError::EnvironmentStartError(err)) => {
@@ -438,14 +1659,68 @@ impl LoginForm {
                                         );
                                         send_ui_request(UIThreadRequest::EnableTui);
 
-                                        status_message
-                                            .set(ErrorStatusMessage::FailedGraphicalEnvironment);
+                                        status_message.set(match err {
+                                            EnvironmentStartError::ScriptNotExecutable(_) => {
+                                                ErrorStatusMessage::SessionScriptNotExecutable
+                                            }
+                                            EnvironmentStartError::NoInteractiveShell(_) => {
+                                                ErrorStatusMessage::NoInteractiveShell
+                                            }
+                                            EnvironmentStartError::WrapperNotFound(_) => {
+                                                ErrorStatusMessage::SessionWrapperNotFound
+                                            }
+                                            _ => ErrorStatusMessage::FailedGraphicalEnvironment,
+                                        });
                                         send_ui_request(UIThreadRequest::Redraw);
                                     }
                                 }
+
+                                if let Some(title) = &session_title {
+                                    if is_session_failure {
+                                        let count =
+                                            session_failures.entry(title.clone()).or_default();
+                                        *count += 1;
+
+                                        let max_session_failures =
+                                            config.failure_fallback.max_session_failures;
+                                        if max_session_failures > 0
+                                            && *count >= max_session_failures
+                                        {
+                                            *count = 0;
+                                            self.select_safe_session(title);
+                                            send_ui_request(UIThreadRequest::Redraw);
+                                        }
+                                    } else if is_started {
+                                        session_failures.remove(title);
+                                    }
+                                }
+
+                                let max_failures = config.failure_fallback.max_failures;
+                                if is_failure {
+                                    failures += 1;
+
+                                    if max_failures > 0 && failures >= max_failures {
+                                        failures = 0;
+                                        self.failure_fallback(&send_ui_request);
+                                    }
+                                }
+                            }
+                        }
+                        // Typing starts the username, without moving to its field first
+                        (KeyCode::Char(_), InputMode::Normal)
+                            if self.config.username_field.focus_on_typing
+                                && !key.modifiers.contains(KeyModifiers::CONTROL)
+                                && input_mode.fields.contains(&InputMode::Username) =>
+                        {
+                            input_mode.set(InputMode::Username);
+                            if let Some(status_msg) = self.username_key_press(key) {
+                                status_message.set(status_msg);
                             }
                         }
                         (KeyCode::Char('s'), InputMode::Normal) => self.set_cache(),
+                        (KeyCode::Enter, InputMode::Switcher)
+                            if self.config.environment_switcher.enter_behaviour
+                                == SwitcherEnterBehaviour::Stay => {}
                         (KeyCode::Enter | KeyCode::Down, _) => {
                             input_mode.next();
                         }
@@ -464,7 +1739,7 @@ impl LoginForm {
                         (KeyCode::Esc, InputMode::Normal) => {
                             if self.preview {
                                 info!("Pressed escape in preview mode to exit the application");
-                                req_send_channel.send(UIThreadRequest::StopDrawing).unwrap();
+                                send_ui_request(UIThreadRequest::StopDrawing);
                             }
                         }
 
@@ -472,6 +1747,12 @@ impl LoginForm {
                             input_mode.set(InputMode::Normal);
                         }
 
+                        // None of the fields use these keys, so the message of the day can always
+                        // be scrolled.
+                        (KeyCode::PageUp | KeyCode::PageDown, _) => {
+                            self.widgets.motd_guard().key_press(key.code);
+                        }
+
                         // For the different input modes the key should be passed to the corresponding
                         // widget.
                         (k, mode) => {
@@ -479,9 +1760,9 @@ impl LoginForm {
                                 InputMode::Switcher => {
                                     self.widgets.environment_guard().key_press(k)
                                 }
-                                InputMode::Username => self.widgets.username_guard().key_press(k),
-                                InputMode::Password => self.widgets.password_guard().key_press(k),
-                                InputMode::Normal => self.widgets.power_menu.key_press(k),
+                                InputMode::Username => self.username_key_press(key),
+                                InputMode::Password => self.widgets.password_guard().key_press(key),
+                                InputMode::Normal => self.widgets.power_menu_guard().key_press(k),
                             };
 
                             // We don't wanna clear any existing error messages
@@ -490,6 +1771,25 @@ impl LoginForm {
                             }
                         }
                     };
+
+                    // The user may have changed, so select their last environment
+                    if previous_input_mode == InputMode::Username
+                        && input_mode.get() != InputMode::Username
+                    {
+                        self.load_user_environment();
+                    }
+
+                    // Once an environment is chosen with Enter, a variant of it can be chosen.
+                    // Moving on in another way goes straight to the next field.
+                    if previous_input_mode == InputMode::Switcher
+                        && input_mode.get() != InputMode::Switcher
+                        && key.code == KeyCode::Enter
+                    {
+                        self.show_variant_picker();
+                    }
+
+                    // Handling the key may have taken long, e.g. for a whole session
+                    last_input = Instant::now();
                 }
 
                 send_ui_request(UIThreadRequest::Redraw);
@@ -499,95 +1799,900 @@ impl LoginForm {
         // Start the UI thread. This actually draws to the screen.
         //
         // This blocks until we actually call StopDrawing
-        while let Ok(request) = req_recv_channel.recv() {
+        let mut tick = 0;
+        let mut is_tui_enabled = true;
+        let mut exit = FormExit::Quit;
+        loop {
+            let request = match req_recv_channel.recv_timeout(SPINNER_INTERVAL) {
+                Ok(request) => request,
+                // The event thread is blocked while e.g. authenticating, so animate the status
+                // message from here.
+                Err(RecvTimeoutError::Timeout) => {
+                    if !is_tui_enabled {
+                        continue;
+                    }
+
+                    let is_in_progress =
+                        matches!(status_message.get(), Some(status) if status.is_in_progress());
+                    if is_in_progress {
+                        tick += 1;
+                        UIThreadRequest::Redraw
+                    } else if widgets.greeting_guard().hour() != greeting_hour {
+                        // Nothing else redraws an idle screen, but the greeting should still change
+                        // when e.g. noon passes
+                        UIThreadRequest::Redraw
+                    } else {
+                        continue;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
             match request {
                 UIThreadRequest::Redraw => {
+                    greeting_hour = widgets.greeting_guard().hour();
                     terminal
                         .draw(|f| {
-                            let layout = Chunks::new(f);
+                            let layout = Chunks::new(f, &widgets.layout_guard(), &fields);
                             login_form_render(
                                 f,
                                 layout,
-                                power_menu.clone(),
-                                environment.clone(),
-                                username.clone(),
-                                password.clone(),
+                                &widgets,
                                 input_mode.get(),
                                 status_message.get(),
+                                &fields,
+                                tick,
                             );
                         })
                         .unwrap();
                 }
                 UIThreadRequest::DisableTui => {
+                    is_tui_enabled = false;
+
+                    if cursor_style.is_some() {
+                        execute!(io::stdout(), SetCursorStyle::RESET)?;
+                    }
+                    if capture_mouse {
+                        execute!(io::stdout(), DisableMouseCapture)?;
+                    }
+
                     disable_raw_mode()?;
-                    execute!(
-                        terminal.backend_mut(),
-                        LeaveAlternateScreen,
-                        Clear(ClearType::All),
-                        MoveTo(0, 0)
-                    )?;
+                    if use_alternate_screen {
+                        execute!(io::stdout(), LeaveAlternateScreen)?;
+                    }
+                    clear_screen(screen_clearing)?;
                     terminal.show_cursor()?;
                 }
                 UIThreadRequest::EnableTui => {
+                    is_tui_enabled = true;
+                    // The output of the session is still on the normal screen
+                    clear_screen(screen_clearing)?;
                     enable_raw_mode()?;
-                    let mut stdout = io::stdout();
-                    execute!(stdout, EnterAlternateScreen)?;
+                    if use_alternate_screen {
+                        execute!(io::stdout(), EnterAlternateScreen)?;
+                    }
+                    if let Some(cursor_style) = cursor_style {
+                        execute!(io::stdout(), cursor_style)?;
+                    }
+                    if capture_mouse {
+                        execute!(io::stdout(), EnableMouseCapture)?;
+                    }
                     terminal.clear()?;
                 }
-                _ => break,
+                UIThreadRequest::Reexec => {
+                    exit = FormExit::Reexec;
+                    break;
+                }
+                UIThreadRequest::StopDrawing => break,
             }
         }
 
-        Ok(())
+        if capture_mouse {
+            execute!(io::stdout(), DisableMouseCapture)?;
+        }
+
+        Ok(exit)
+    }
+}
+
+/// The message of a caught panic
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown"
+    }
+}
+
+/// Clear the VT as configured with `clear_on_session_switch`
+fn clear_screen(clearing: ScreenClearing) -> io::Result<()> {
+    match clearing {
+        ScreenClearing::None => Ok(()),
+        ScreenClearing::Screen => execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0)),
+        ScreenClearing::Scrollback => execute!(
+            io::stdout(),
+            Clear(ClearType::Purge),
+            Clear(ClearType::All),
+            MoveTo(0, 0)
+        ),
     }
 }
 
-#[allow(clippy::too_many_arguments)]
 fn login_form_render<B: Backend>(
     frame: &mut Frame<B>,
     chunks: Chunks,
-    power_menu: PowerMenuWidget,
-    environment: Arc<Mutex<SwitcherWidget<PostLoginEnvironment>>>,
-    username: Arc<Mutex<InputFieldWidget>>,
-    password: Arc<Mutex<InputFieldWidget>>,
+    widgets: &Widgets,
     input_mode: InputMode,
     status_message: Option<StatusMessage>,
+    fields: &[InputMode],
+    tick: usize,
 ) {
-    power_menu.render(frame, chunks.power_menu);
-    environment
-        .lock()
-        .unwrap_or_else(|err| {
-            error!("Failed to lock post-login environment. Reason: {}", err);
-            std::process::exit(1);
-        })
-        .render(
-            frame,
-            chunks.switcher,
-            matches!(input_mode, InputMode::Switcher),
+    widgets.power_menu_guard().render(frame, chunks.power_menu);
+    widgets.greeting_guard().render(frame, chunks.power_menu);
+    for field in fields {
+        let is_focused = input_mode == *field;
+
+        match field {
+            InputMode::Switcher => {
+                widgets
+                    .environment_guard()
+                    .render(frame, chunks.switcher, is_focused)
+            }
+            InputMode::Username => {
+                widgets
+                    .username_guard()
+                    .render(frame, chunks.username_field, is_focused)
+            }
+            InputMode::Password => {
+                widgets
+                    .password_guard()
+                    .render(frame, chunks.password_field, is_focused)
+            }
+            InputMode::Normal => {}
+        }
+    }
+
+    // Display Status Message
+    StatusMessage::render(
+        status_message,
+        frame,
+        chunks.status_message,
+        tick,
+        &widgets.status_colors_guard(),
+    );
+
+    let motd_area = match widgets.keyboard_guard().as_mut() {
+        Some(keyboard) => {
+            let keyboard_height = chunks.motd.height.min(KEYBOARD_HEIGHT);
+            keyboard.render(
+                frame,
+                Rect {
+                    height: keyboard_height,
+                    ..chunks.motd
+                },
+            );
+
+            Rect {
+                y: chunks.motd.y + keyboard_height,
+                height: chunks.motd.height - keyboard_height,
+                ..chunks.motd
+            }
+        }
+        None => chunks.motd,
+    };
+    widgets.motd_guard().render(frame, motd_area);
+
+    // With a vertical margin, the bottom line is otherwise empty
+    let size = frame.size();
+    let horizontal_margin = widgets.layout_guard().horizontal_margin;
+    let bottom_line = Rect {
+        x: size.x + horizontal_margin,
+        y: size.bottom().saturating_sub(1),
+        width: size.width.saturating_sub(2 * horizontal_margin),
+        height: size.height.min(1),
+    };
+
+    if let Some(style) = *widgets.breadcrumb_guard() {
+        let breadcrumb = Paragraph::new(breadcrumb(input_mode, fields, style));
+        frame.render_widget(breadcrumb, bottom_line);
+    }
+
+    if let Some(style) = *widgets.version_guard() {
+        let version = Paragraph::new(Span::styled(
+            concat!("lemurs v", env!("CARGO_PKG_VERSION")),
+            style,
+        ))
+        .alignment(Alignment::Right);
+        frame.render_widget(version, bottom_line);
+    }
+
+    if let Some(session_details) = widgets.session_details_guard().as_ref() {
+        session_details.render(frame);
+    }
+
+    if let Some(variant_picker) = widgets.variant_picker_guard().as_ref() {
+        variant_picker.render(frame);
+    }
+
+    if widgets.basic_terminal {
+        let area = frame.size();
+        frame.render_widget(StripColors, area);
+    }
+}
+
+/// Removes the colors from an area, while keeping the modifiers (e.g. bold)
+struct StripColors;
+
+impl Widget for StripColors {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        buf.set_style(area, Style::default().fg(Color::Reset).bg(Color::Reset));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    use crossterm::event::KeyEvent;
+    use tui::backend::TestBackend;
+
+    use crate::info_caching::MemoryCache;
+
+    /// Events that are fed to the login form one-by-one
+    struct ScriptedEvents(VecDeque<Event>);
+
+    impl ScriptedEvents {
+        fn new(keys: &[KeyEvent]) -> Self {
+            Self(keys.iter().copied().map(Event::Key).collect())
+        }
+    }
+
+    impl EventSource for ScriptedEvents {
+        fn poll(&mut self, _timeout: Duration) -> io::Result<Option<Event>> {
+            Ok(self.0.pop_front())
+        }
+
+        fn is_exhausted(&self) -> bool {
+            self.0.is_empty()
+        }
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn keys(text: &str) -> Vec<KeyEvent> {
+        text.chars().map(|c| key(KeyCode::Char(c))).collect()
+    }
+
+    /// Run a login form in preview mode with the given keys and return the final state
+    fn run_with_keys(config: Config, keys: &[KeyEvent]) -> LoginForm {
+        run_with_keys_in_mode(config, keys, true)
+    }
+
+    /// Run a login form with the given keys and return the final state
+    ///
+    /// The keys should never submit the form when not in preview mode.
+    fn run_with_keys_in_mode(config: Config, keys: &[KeyEvent], preview: bool) -> LoginForm {
+        let login_form = LoginForm::new(config, preview, None);
+        run_form_with_keys(login_form, keys)
+    }
+
+    fn run_form_with_keys(login_form: LoginForm, keys: &[KeyEvent]) -> LoginForm {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let state = login_form.clone();
+
+        login_form
+            .run(&mut terminal, ScriptedEvents::new(keys), false)
+            .unwrap();
+
+        state
+    }
+
+    fn test_config() -> Config {
+        // Make sure that the cache on the system running the tests has no influence
+        let mut config = Config {
+            focus_behaviour: FocusBehaviour::NoFocus,
+            ..Config::default()
+        };
+        config.environment_switcher.remember = false;
+        config.environment_switcher.remember_per_user = false;
+        config.username_field.remember = false;
+        config.environment_switcher.hide_single = false;
+
+        config
+    }
+
+    mod input_mode {
+        use super::*;
+        use InputMode::*;
+
+        fn next(mode: InputMode, fields: &[InputMode]) -> InputMode {
+            let mut mode = mode;
+            mode.next(fields);
+            mode
+        }
+
+        fn prev(mode: InputMode, fields: &[InputMode]) -> InputMode {
+            let mut mode = mode;
+            mode.prev(fields);
+            mode
+        }
+
+        #[test]
+        fn all_visible() {
+            let fields = InputMode::FIELDS;
+
+            assert_eq!(next(Normal, &fields), Switcher);
+            assert_eq!(next(Switcher, &fields), Username);
+            assert_eq!(next(Username, &fields), Password);
+            assert_eq!(next(Password, &fields), Password);
+
+            assert_eq!(prev(Normal, &fields), Normal);
+            assert_eq!(prev(Switcher, &fields), Normal);
+            assert_eq!(prev(Username, &fields), Switcher);
+            assert_eq!(prev(Password, &fields), Username);
+        }
+
+        #[test]
+        fn hidden_switcher() {
+            let fields = [Username, Password];
+
+            assert_eq!(next(Normal, &fields), Username);
+            assert_eq!(next(Username, &fields), Password);
+            assert_eq!(next(Password, &fields), Password);
+
+            assert_eq!(prev(Password, &fields), Username);
+            assert_eq!(prev(Username, &fields), Normal);
+            assert_eq!(prev(Normal, &fields), Normal);
+        }
+
+        #[test]
+        fn hidden_username() {
+            let fields = [Switcher, Password];
+
+            assert_eq!(next(Normal, &fields), Switcher);
+            assert_eq!(next(Switcher, &fields), Password);
+            assert_eq!(next(Password, &fields), Password);
+
+            assert_eq!(prev(Password, &fields), Switcher);
+            assert_eq!(prev(Switcher, &fields), Normal);
+        }
+
+        #[test]
+        fn hidden_switcher_and_username() {
+            let fields = [Password];
+
+            assert_eq!(next(Normal, &fields), Password);
+            assert_eq!(next(Password, &fields), Password);
+
+            assert_eq!(prev(Password, &fields), Normal);
+            assert_eq!(prev(Normal, &fields), Normal);
+        }
+
+        #[test]
+        fn custom_order() {
+            let fields = [Password, Switcher, Username];
+
+            assert_eq!(next(Normal, &fields), Password);
+            assert_eq!(next(Password, &fields), Switcher);
+            assert_eq!(next(Switcher, &fields), Username);
+            assert_eq!(next(Username, &fields), Username);
+
+            assert_eq!(prev(Username, &fields), Switcher);
+            assert_eq!(prev(Password, &fields), Normal);
+        }
+
+        #[test]
+        fn set_skips_hidden() {
+            let input_mode = LoginFormInputMode::new(vec![Username, Password]);
+
+            input_mode.set(Switcher);
+            assert_eq!(input_mode.get(), Username);
+
+            input_mode.set(Normal);
+            assert_eq!(input_mode.get(), Normal);
+
+            input_mode.next();
+            assert_eq!(input_mode.get(), Username);
+
+            input_mode.prev();
+            assert_eq!(input_mode.get(), Normal);
+        }
+
+        #[test]
+        fn fields_from_config() {
+            let mut config = test_config();
+            assert_eq!(login_fields(&config, 2), InputMode::FIELDS);
+
+            config.field_order = vec![
+                LoginField::Password,
+                LoginField::Password,
+                LoginField::Environment,
+            ];
+            assert_eq!(login_fields(&config, 2), [Password, Switcher, Username]);
+
+            config.username_field.use_fixed_username = true;
+            config.field_order = vec![LoginField::Username];
+            assert_eq!(login_fields(&config, 2), [Password]);
+        }
+
+        #[test]
+        fn single_environment() {
+            let mut config = test_config();
+            assert_eq!(login_fields(&config, 1), InputMode::FIELDS);
+
+            config.environment_switcher.hide_single = true;
+            assert_eq!(login_fields(&config, 1), [Username, Password]);
+            assert_eq!(login_fields(&config, 2), InputMode::FIELDS);
+        }
+    }
+
+    #[test]
+    fn initial_focus() {
+        use InputMode::*;
+
+        let cached = |environment, username| CachedFields {
+            environment,
+            username,
+        };
+        let first_non_cached = FocusBehaviour::FirstNonCached;
+
+        assert_eq!(
+            initial_input_mode(&first_non_cached, cached(false, false)),
+            Switcher
+        );
+        assert_eq!(
+            initial_input_mode(&first_non_cached, cached(false, true)),
+            Switcher
         );
-    username
-        .lock()
-        .unwrap_or_else(|err| {
-            error!("Failed to lock username. Reason: {}", err);
-            std::process::exit(1);
-        })
-        .render(
-            frame,
-            chunks.username_field,
-            matches!(input_mode, InputMode::Username),
+        assert_eq!(
+            initial_input_mode(&first_non_cached, cached(true, false)),
+            Username
         );
-    password
-        .lock()
-        .unwrap_or_else(|err| {
-            error!("Failed to lock password. Reason: {}", err);
-            std::process::exit(1);
-        })
-        .render(
-            frame,
-            chunks.password_field,
-            matches!(input_mode, InputMode::Password),
+        assert_eq!(
+            initial_input_mode(&first_non_cached, cached(true, true)),
+            Password
         );
 
-    // Display Status Message
-    StatusMessage::render(status_message, frame, chunks.status_message);
+        assert_eq!(
+            initial_input_mode(&FocusBehaviour::NoFocus, cached(true, true)),
+            Normal
+        );
+        assert_eq!(
+            initial_input_mode(&FocusBehaviour::Username, cached(true, true)),
+            Username
+        );
+    }
+
+    #[test]
+    fn typing_credentials() {
+        let mut input = vec![key(KeyCode::Down), key(KeyCode::Down)];
+        input.extend(keys("lemur"));
+        input.push(key(KeyCode::Enter));
+        input.extend(keys("secret"));
+
+        let state = run_with_keys(test_config(), &input);
+
+        assert_eq!(state.input_mode.get(), InputMode::Password);
+        assert_eq!(state.widgets.get_username(), "lemur");
+        assert_eq!(state.widgets.get_password(), "secret");
+    }
+
+    #[test]
+    fn missing_environment() {
+        let mut config = test_config();
+        config.environment_switcher.default_environment = "sway".to_string();
+
+        config.environment_switcher.missing_environment = MissingEnvironment::First;
+        let form = LoginForm::new(config.clone(), true, None);
+        assert!(form.select_cached_environment("removed"));
+        assert_eq!(form.widgets.get_environment().unwrap().0, "bspwm");
+
+        config.environment_switcher.missing_environment = MissingEnvironment::Default;
+        let form = LoginForm::new(config.clone(), true, None);
+        assert!(form.select_cached_environment("removed"));
+        assert_eq!(form.widgets.get_environment().unwrap().0, "sway");
+
+        config.environment_switcher.missing_environment = MissingEnvironment::Notice;
+        let form = LoginForm::new(config, true, None);
+        assert!(!form.select_cached_environment("removed"));
+        assert!(form.status_message.get().is_some());
+    }
+
+    #[test]
+    fn host_default_environment() {
+        let mut config = test_config().environment_switcher;
+        config.default_environment = "i3".to_string();
+        assert_eq!(
+            default_environment(&config, Some("lab-pc-01"), "seat0"),
+            "i3"
+        );
+
+        config.host_default_environments = HashMap::from([
+            ("lab-pc-01".to_string(), "sway".to_string()),
+            ("seat1".to_string(), "TTYSHELL".to_string()),
+        ]);
+        assert_eq!(
+            default_environment(&config, Some("lab-pc-01"), "seat1"),
+            "sway"
+        );
+        assert_eq!(
+            default_environment(&config, Some("lab-pc-02"), "seat1"),
+            "TTYSHELL"
+        );
+        assert_eq!(default_environment(&config, None, "seat0"), "i3");
+    }
+
+    #[test]
+    fn safe_session() {
+        let mut config = test_config();
+        config.failure_fallback.safe_session = "sway".to_string();
+
+        let form = LoginForm::new(config.clone(), true, None);
+        form.select_safe_session("bspwm");
+        assert_eq!(form.widgets.get_environment().unwrap().0, "sway");
+        assert!(form.status_message.get().is_some());
+
+        // The safe session itself failing has nothing to fall back to
+        config.failure_fallback.safe_session = "bspwm".to_string();
+        let form = LoginForm::new(config, true, None);
+        form.select_safe_session("bspwm");
+        assert!(form.status_message.get().is_none());
+    }
+
+    #[test]
+    fn session_details_capture_keys() {
+        let mut input = vec![key(KeyCode::Down), key(KeyCode::Down), key(KeyCode::F(3))];
+        input.extend(keys("ab"));
+
+        let state = run_with_keys(test_config(), &input);
+        assert!(state.widgets.session_details_guard().is_some());
+        assert_eq!(state.widgets.get_username(), "");
+
+        input.push(key(KeyCode::Esc));
+        input.extend(keys("cd"));
+
+        let state = run_with_keys(test_config(), &input);
+        assert!(state.widgets.session_details_guard().is_none());
+        assert_eq!(state.widgets.get_username(), "cd");
+    }
+
+    #[test]
+    fn username_advances() {
+        let mut config = test_config();
+        config.username_field.advance_characters = ";".to_string();
+
+        let mut input = vec![key(KeyCode::Down), key(KeyCode::Down)];
+        input.extend(keys("badge;secret"));
+        let state = run_with_keys(config.clone(), &input);
+        assert_eq!(state.widgets.get_username(), "badge");
+        assert_eq!(state.widgets.get_password(), "secret");
+
+        config.username_field.advance_length = 3;
+        let mut input = vec![key(KeyCode::Down), key(KeyCode::Down)];
+        input.extend(keys("abcdef"));
+        let state = run_with_keys(config, &input);
+        assert_eq!(state.widgets.get_username(), "abc");
+        assert_eq!(state.widgets.get_password(), "def");
+    }
+
+    #[test]
+    fn variant_picker_on_enter() {
+        fn two_variants(environment: &PostLoginEnvironment) -> Vec<(String, PostLoginEnvironment)> {
+            vec![
+                ("default".to_string(), environment.clone()),
+                ("debug".to_string(), environment.clone()),
+            ]
+        }
+
+        // Tabbing past the environment does not swallow the username
+        let login_form = LoginForm::new(test_config(), true, None).with_variants(two_variants);
+        let mut input = vec![key(KeyCode::Tab), key(KeyCode::Tab)];
+        input.extend(keys("lemur"));
+        let state = run_form_with_keys(login_form, &input);
+        assert!(state.widgets.variant_picker_guard().is_none());
+        assert_eq!(state.input_mode.get(), InputMode::Username);
+        assert_eq!(state.widgets.get_username(), "lemur");
+
+        let login_form = LoginForm::new(test_config(), true, None).with_variants(two_variants);
+        let input = [key(KeyCode::Tab), key(KeyCode::Enter)];
+        let state = run_form_with_keys(login_form, &input);
+        assert!(state.widgets.variant_picker_guard().is_some());
+    }
+
+    #[test]
+    fn focus_on_typing() {
+        let mut config = test_config();
+        let input = keys("abc");
+
+        let state = run_with_keys(config.clone(), &input);
+        assert_eq!(state.widgets.get_username(), "");
+        assert_eq!(state.input_mode.get(), InputMode::Normal);
+
+        config.username_field.focus_on_typing = true;
+        let state = run_with_keys(config.clone(), &input);
+        assert_eq!(state.widgets.get_username(), "abc");
+        assert_eq!(state.input_mode.get(), InputMode::Username);
+
+        // The shortcut to remember the username is taken over as well
+        let state = run_with_keys(config, &keys("sam"));
+        assert_eq!(state.widgets.get_username(), "sam");
+    }
+
+    #[test]
+    fn panic_messages() {
+        let payload = panic::catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "static");
+
+        let number = 42;
+        let payload = panic::catch_unwind(|| panic!("formatted {number}")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "formatted 42");
+    }
+
+    #[test]
+    fn breadcrumb_text() {
+        let text = |input_mode| {
+            breadcrumb(input_mode, &InputMode::FIELDS, Style::default())
+                .0
+                .into_iter()
+                .map(|span| span.content)
+                .collect::<String>()
+        };
+
+        assert_eq!(
+            text(InputMode::Username),
+            "Menu > Session > [Username] > Password   Tab: next  Shift-Tab: back"
+        );
+        assert_eq!(
+            text(InputMode::Normal),
+            "[Menu] > Session > Username > Password   Tab: next"
+        );
+        assert_eq!(
+            text(InputMode::Password),
+            "Menu > Session > Username > [Password]   Shift-Tab: back"
+        );
+    }
+
+    #[test]
+    fn remembered_information() {
+        let mut config = test_config();
+        config.environment_switcher.remember = true;
+        config.username_field.remember = true;
+
+        let cache = Arc::new(MemoryCache::new(Some("sway"), Some("lemur")));
+        let form = LoginForm::new(config.clone(), true, None).with_cache(cache.clone());
+        let state = run_form_with_keys(form, &[]);
+        assert_eq!(state.widgets.get_username(), "lemur");
+        assert_eq!(state.widgets.get_environment().unwrap().0, "sway");
+
+        // Choose the other environment and save it with 's'
+        let form = LoginForm::new(config, true, None).with_cache(cache.clone());
+        let input = [
+            key(KeyCode::Down),
+            key(KeyCode::Left),
+            key(KeyCode::Esc),
+            key(KeyCode::Char('s')),
+        ];
+        run_form_with_keys(form, &input);
+
+        let cached = cache.get();
+        assert_eq!(cached.environment(), Some("bspwm"));
+        assert_eq!(cached.username(), Some("lemur"));
+    }
+
+    /// The keys to login as `lemur` with the default fields
+    fn login_keys() -> Vec<KeyEvent> {
+        let mut input = vec![key(KeyCode::Tab), key(KeyCode::Tab)];
+        input.extend(keys("lemur"));
+        input.push(key(KeyCode::Tab));
+        input.extend(keys("password"));
+        input.push(key(KeyCode::Enter));
+        input
+    }
+
+    #[test]
+    fn remembered_on_login() {
+        fn login(
+            _: &str,
+            _: &str,
+            _: &PostLoginEnvironment,
+            hooks: &Hooks<'_>,
+            _: &Config,
+        ) -> Result<(), StartSessionError> {
+            // This is where the environment would be started
+            if let Some(pre_environment) = hooks.pre_environment {
+                pre_environment();
+            }
+            Ok(())
+        }
+
+        let mut config = test_config();
+        config.environment_switcher.remember = true;
+        config.username_field.remember = true;
+        config.clear_on_session_switch = ScreenClearing::None;
+
+        let cache = Arc::new(MemoryCache::new(None, None));
+        let form = LoginForm::new(config, false, None)
+            .with_cache(cache.clone())
+            .with_start_session(login);
+        let state = run_form_with_keys(form, &login_keys());
+
+        let cached = cache.get();
+        assert_eq!(cached.username(), Some("lemur"));
+        assert_eq!(
+            cached.environment(),
+            state
+                .widgets
+                .get_environment()
+                .map(|(title, _)| title)
+                .as_deref()
+        );
+    }
+
+    #[test]
+    fn not_remembered_on_failure() {
+        fn login(
+            _: &str,
+            _: &str,
+            _: &PostLoginEnvironment,
+            _: &Hooks<'_>,
+            _: &Config,
+        ) -> Result<(), StartSessionError> {
+            Err(StartSessionError::AuthenticationError(
+                crate::auth::AuthenticationError::UsernameNotFound,
+            ))
+        }
+
+        let mut config = test_config();
+        config.environment_switcher.remember = true;
+        config.username_field.remember = true;
+
+        let cache = Arc::new(MemoryCache::new(Some("sway"), None));
+        let form = LoginForm::new(config, false, None)
+            .with_cache(cache.clone())
+            .with_start_session(login);
+        run_form_with_keys(form, &login_keys());
+
+        let cached = cache.get();
+        assert_eq!(cached.environment(), Some("sway"));
+        assert_eq!(cached.username(), None);
+    }
+
+    #[test]
+    fn nothing_to_remember() {
+        let cache = Arc::new(MemoryCache::new(Some("sway"), Some("lemur")));
+        let form = LoginForm::new(test_config(), true, None).with_cache(cache.clone());
+        run_form_with_keys(form, &[key(KeyCode::Char('s'))]);
+
+        let cached = cache.get();
+        assert_eq!(cached.environment(), Some("sway"));
+        assert_eq!(cached.username(), Some("lemur"));
+    }
+
+    #[test]
+    fn initial_session() {
+        let form = LoginForm::new(test_config(), true, None)
+            .with_initial_session(Some("sway".to_string()));
+        assert_eq!(form.initial_session.as_deref(), Some("sway"));
+        assert_eq!(form.widgets.get_environment().unwrap().0, "sway");
+
+        let form = LoginForm::new(test_config(), true, None)
+            .with_initial_session(Some("removed".to_string()));
+        assert_eq!(form.initial_session, None);
+        assert_eq!(form.widgets.get_environment().unwrap().0, "bspwm");
+    }
+
+    #[test]
+    fn enter_in_switcher() {
+        let mut config = test_config();
+        let input = [key(KeyCode::Down), key(KeyCode::Enter)];
+
+        let state = run_with_keys(config.clone(), &input);
+        assert_eq!(state.input_mode.get(), InputMode::Username);
+
+        config.environment_switcher.enter_behaviour = SwitcherEnterBehaviour::Stay;
+        let state = run_with_keys(config, &input);
+        assert_eq!(state.input_mode.get(), InputMode::Switcher);
+    }
+
+    #[test]
+    fn escape_leaves_fields() {
+        let mut input = vec![key(KeyCode::Tab), key(KeyCode::Tab)];
+        input.extend(keys("ab"));
+        input.push(key(KeyCode::Esc));
+        input.extend(keys("cd"));
+
+        let state = run_with_keys(test_config(), &input);
+
+        assert_eq!(state.input_mode.get(), InputMode::Normal);
+        assert_eq!(state.widgets.get_username(), "ab");
+        assert_eq!(state.widgets.get_password(), "");
+    }
+
+    #[test]
+    fn fixed_username_is_skipped() {
+        let mut config = test_config();
+        config.username_field.use_fixed_username = true;
+        config.username_field.fixed_username = "lemur".to_string();
+
+        let mut input = vec![key(KeyCode::Down), key(KeyCode::Down)];
+        input.extend(keys("secret"));
+
+        let state = run_with_keys(config, &input);
+
+        assert_eq!(state.input_mode.get(), InputMode::Password);
+        assert_eq!(state.widgets.get_username(), "lemur");
+        assert_eq!(state.widgets.get_password(), "secret");
+    }
+
+    #[test]
+    fn ctrl_c_quits_preview() {
+        let mut input = vec![key(KeyCode::Down), key(KeyCode::Down)];
+        input.extend(keys("ab"));
+        input.push(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        input.extend(keys("cd"));
+
+        let state = run_with_keys(test_config(), &input);
+        assert_eq!(state.widgets.get_username(), "ab");
+
+        // Outside of the preview, Ctrl-C should neither quit nor type anything
+        let state = run_with_keys_in_mode(test_config(), &input, false);
+        assert_eq!(state.widgets.get_username(), "abcd");
+    }
+
+    /// No events for a while, after which the hour of the clock is changed
+    struct IdleEvents {
+        polls_left: u32,
+        hour: Arc<AtomicI32>,
+    }
+
+    impl EventSource for IdleEvents {
+        fn poll(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+            self.hour.store(12, Ordering::SeqCst);
+            self.polls_left -= 1;
+            std::thread::sleep(timeout);
+            Ok(None)
+        }
+
+        fn is_exhausted(&self) -> bool {
+            self.polls_left == 0
+        }
+    }
+
+    #[test]
+    fn greeting_changes_while_idle() {
+        let mut config = test_config();
+        config.greeting.show_greeting = true;
+
+        let hour = Arc::new(AtomicI32::new(11));
+        let clock_hour = hour.clone();
+        let login_form = LoginForm::new(config.clone(), true, None);
+        *login_form.widgets.greeting_guard() = GreetingWidget::with_clock(
+            config.greeting,
+            Arc::new(move || Some(clock_hour.load(Ordering::SeqCst))),
+        );
+
+        // Idle long enough for the UI thread to notice the new hour
+        let events = IdleEvents {
+            polls_left: 5,
+            hour,
+        };
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        login_form.run(&mut terminal, events, false).unwrap();
+
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect();
+        assert!(screen.contains("Good afternoon"));
+        assert!(!screen.contains("Good morning"));
+    }
 }
@@ -0,0 +1,283 @@
+use std::fs;
+use std::io::{self, Read};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use crossterm::event::KeyCode;
+use log::{info, warn};
+use tui::layout::Rect;
+use tui::style::Style;
+use tui::text::{Span, Spans, Text};
+use tui::widgets::Paragraph;
+use tui::Frame;
+
+use crate::config::{get_color, get_modifiers, MotdConfig};
+
+/// The most output of the message of the day command that is shown
+const MAX_COMMAND_OUTPUT: u64 = 16 * 1024;
+
+/// Run the message of the day command and return its output
+///
+/// A command that fails shows nothing new, so `None` is returned.
+fn command_output(command: &str) -> Option<Vec<String>> {
+    let mut child = match Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => {
+            warn!(
+                "Failed to run the message of the day command. Reason: {}",
+                err
+            );
+            return None;
+        }
+    };
+
+    let mut output = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        if let Err(err) = stdout.take(MAX_COMMAND_OUTPUT).read_to_end(&mut output) {
+            warn!(
+                "Failed to read the message of the day command. Reason: {}",
+                err
+            );
+        }
+    }
+
+    // The rest of the output is not read, so the command should not wait for it
+    let _ = child.kill();
+    match child.wait() {
+        Ok(status) if status.success() => {}
+        // A command that is stopped after its output was cut off is still fine
+        Ok(_) if output.len() as u64 == MAX_COMMAND_OUTPUT => {}
+        Ok(status) => {
+            warn!("The message of the day command failed with {}", status);
+            return None;
+        }
+        Err(err) => {
+            warn!(
+                "Failed to wait for the message of the day command. Reason: {}",
+                err
+            );
+            return None;
+        }
+    }
+
+    Some(
+        String::from_utf8_lossy(&output)
+            .lines()
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+/// Run the message of the day command of `config` in the background, once and then every
+/// `command_interval` seconds
+///
+/// Every successful run sends its output. Without a command, `None` is returned.
+pub fn watch_command(config: &MotdConfig) -> Option<Receiver<Vec<String>>> {
+    if config.command.is_empty() {
+        return None;
+    }
+
+    let command = config.command.clone();
+    let interval = config.command_interval;
+    let (sender, receiver) = channel();
+    thread::spawn(move || loop {
+        if let Some(lines) = command_output(&command) {
+            // The login form is gone, so nobody is listening anymore
+            if sender.send(lines).is_err() {
+                return;
+            }
+        }
+
+        if interval == 0 {
+            return;
+        }
+        thread::sleep(Duration::from_secs(interval));
+    });
+
+    Some(receiver)
+}
+
+/// The message of the day that is shown below the login fields
+#[derive(Clone)]
+pub struct MotdWidget {
+    lines: Vec<String>,
+    /// Vertical scroll in lines
+    scroll: u16,
+
+    /// Height of the last area the message was rendered in
+    height: u16,
+    config: MotdConfig,
+}
+
+impl MotdWidget {
+    pub fn new(config: MotdConfig) -> Self {
+        let lines = match fs::read_to_string(&config.path) {
+            Ok(content) => content.lines().map(str::to_string).collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                info!("No message of the day found at '{}'", config.path);
+                Vec::new()
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to read message of the day from '{}'. Reason: {}",
+                    config.path, err
+                );
+                Vec::new()
+            }
+        };
+
+        Self::with_lines(lines, config)
+    }
+
+    fn with_lines(mut lines: Vec<String>, config: MotdConfig) -> Self {
+        // Trailing empty lines only take up space
+        while let Some(line) = lines.last() {
+            if !line.trim().is_empty() {
+                break;
+            }
+
+            lines.pop();
+        }
+
+        Self {
+            lines,
+            scroll: 0,
+            height: 0,
+            config,
+        }
+    }
+
+    /// Show `lines` instead of the current message, e.g. the output of the command
+    pub fn set_lines(&mut self, lines: Vec<String>) {
+        let scroll = self.scroll;
+        *self = Self::with_lines(lines, self.config.clone());
+        self.scroll = scroll.min(self.max_scroll());
+    }
+
+    /// Use a new configuration, while keeping the message that is shown
+    pub fn set_config(&mut self, config: MotdConfig) {
+        self.config = config;
+    }
+
+    /// Whether there is no message to show
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    fn max_scroll(&self) -> u16 {
+        let line_count = u16::try_from(self.lines.len()).unwrap_or(u16::MAX);
+        line_count.saturating_sub(self.height)
+    }
+
+    fn style(&self) -> Style {
+        let mut style = Style::default().fg(get_color(&self.config.color));
+
+        for modifier in get_modifiers(&self.config.modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        style
+    }
+
+    pub fn render(&mut self, frame: &mut Frame<impl tui::backend::Backend>, area: Rect) {
+        self.height = area.height;
+        self.scroll = self.scroll.min(self.max_scroll());
+
+        if self.is_empty() {
+            return;
+        }
+
+        let style = self.style();
+        let text = Text::from(
+            self.lines
+                .iter()
+                .map(|line| Spans::from(Span::styled(line.as_str(), style)))
+                .collect::<Vec<_>>(),
+        );
+        let widget = Paragraph::new(text).scroll((self.scroll, 0));
+
+        frame.render_widget(widget, area);
+    }
+
+    pub(crate) fn key_press(&mut self, key_code: KeyCode) -> Option<super::ErrorStatusMessage> {
+        // Keep one line of context when scrolling by a page
+        let page = self.height.saturating_sub(1).max(1);
+
+        match key_code {
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(page),
+            KeyCode::PageDown => {
+                self.scroll = self.scroll.saturating_add(page).min(self.max_scroll())
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn motd(line_count: usize, height: u16) -> MotdWidget {
+        let lines = (0..line_count).map(|i| format!("line {i}")).collect();
+        let mut motd = MotdWidget::with_lines(lines, Config::default().motd);
+        motd.height = height;
+        motd
+    }
+
+    #[test]
+    fn scrolling() {
+        let mut motd = motd(10, 4);
+
+        motd.key_press(KeyCode::PageDown);
+        assert_eq!(motd.scroll, 3);
+        motd.key_press(KeyCode::PageDown);
+        assert_eq!(motd.scroll, 6);
+        motd.key_press(KeyCode::PageDown);
+        assert_eq!(motd.scroll, 6);
+
+        motd.key_press(KeyCode::PageUp);
+        assert_eq!(motd.scroll, 3);
+        motd.key_press(KeyCode::PageUp);
+        motd.key_press(KeyCode::PageUp);
+        assert_eq!(motd.scroll, 0);
+    }
+
+    #[test]
+    fn short_message_does_not_scroll() {
+        let mut motd = motd(3, 4);
+
+        motd.key_press(KeyCode::PageDown);
+        assert_eq!(motd.scroll, 0);
+    }
+
+    #[test]
+    fn message_command() {
+        assert_eq!(
+            command_output("echo first; echo second"),
+            Some(vec!["first".to_string(), "second".to_string()])
+        );
+        assert_eq!(command_output("echo partial; exit 1"), None);
+
+        let output = command_output("yes").unwrap();
+        let length: usize = output.iter().map(|line| line.len() + 1).sum();
+        assert_eq!(length as u64, MAX_COMMAND_OUTPUT);
+    }
+
+    #[test]
+    fn empty_message() {
+        let lines = vec![String::new(), "  ".to_string()];
+        assert!(MotdWidget::with_lines(lines, Config::default().motd).is_empty());
+    }
+}
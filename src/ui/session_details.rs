@@ -0,0 +1,93 @@
+use tui::layout::{Alignment, Rect};
+use tui::style::{Modifier, Style};
+use tui::text::{Span, Spans, Text};
+use tui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
+use tui::Frame;
+
+/// An overlay which shows how the selected environment is started
+#[derive(Clone)]
+pub struct SessionDetailsWidget {
+    title: String,
+    details: Vec<(&'static str, String)>,
+}
+
+/// An area of at most `width` by `height` in the center of `area`
+pub(super) fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+impl SessionDetailsWidget {
+    pub fn new(title: String, details: Vec<(&'static str, String)>) -> Self {
+        Self { title, details }
+    }
+
+    pub fn render(&self, frame: &mut Frame<impl tui::backend::Backend>) {
+        let label_width = self
+            .details
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut lines: Vec<Spans> = self
+            .details
+            .iter()
+            .map(|(label, value)| {
+                Spans::from(vec![
+                    Span::styled(
+                        format!("{label:<label_width$}  "),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(value.as_str()),
+                ])
+            })
+            .collect();
+        lines.push(Spans::default());
+        lines.push(Spans::from(Span::styled(
+            "Press Esc to close",
+            Style::default().add_modifier(Modifier::DIM),
+        )));
+
+        let content_width = lines.iter().map(Spans::width).max().unwrap_or(0);
+        let size = frame.size();
+        let area = centered(
+            size,
+            u16::try_from(content_width + 4).unwrap_or(u16::MAX),
+            u16::try_from(lines.len() + 2).unwrap_or(u16::MAX),
+        );
+
+        let widget = Paragraph::new(Text::from(lines))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {} ", self.title)),
+            )
+            .alignment(Alignment::Left)
+            .wrap(Wrap { trim: false });
+
+        // Otherwise, the form shines through the empty cells
+        frame.render_widget(Clear, area);
+        frame.render_widget(widget, area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centered_area() {
+        let area = Rect::new(0, 0, 80, 24);
+
+        assert_eq!(centered(area, 40, 10), Rect::new(20, 7, 40, 10));
+        assert_eq!(centered(area, 100, 30), area);
+    }
+}
@@ -1,33 +1,94 @@
 use tui::backend::Backend;
 use tui::layout::Rect;
-use tui::style::Color;
+use tui::style::{Color, Style};
 use tui::widgets::Paragraph;
 use tui::Frame;
 
+use super::input_field::ConfirmationError;
 use crate::auth::AuthenticationError;
+use crate::config::{get_color, StatusMessageConfig};
 
 #[derive(Clone)]
 pub enum ErrorStatusMessage {
     AuthenticationError(AuthenticationError),
+    LoginRefused,
+    AlreadyLoggedIn,
     NoGraphicalEnvironment,
     FailedGraphicalEnvironment,
+    SessionScriptNotExecutable,
+    NoInteractiveShell,
+    SessionWrapperNotFound,
     FailedDesktop,
     FailedShutdown,
     FailedReboot,
+    FailedConsoleSwitch,
+    /// Something that should never happen went wrong while logging in
+    UnexpectedFailure,
+    /// Logging in failed too many times. Contains the recovery instructions.
+    TooManyFailures(String),
+    /// Logins are disabled. Contains the reason.
+    NoLogin(String),
+    /// A session failed to start too often, so the safe session is selected instead. Contains the
+    /// titles of both sessions.
+    SafeSessionSelected {
+        failed: String,
+        safe: String,
+    },
+    #[allow(dead_code)] // Not used by a form yet
+    Confirmation(ConfirmationError),
 }
 
-impl From<ErrorStatusMessage> for &'static str {
+impl From<ConfirmationError> for ErrorStatusMessage {
+    fn from(err: ConfirmationError) -> Self {
+        Self::Confirmation(err)
+    }
+}
+
+impl From<ErrorStatusMessage> for String {
     fn from(err: ErrorStatusMessage) -> Self {
         use ErrorStatusMessage::*;
 
-        match err {
+        let message = match err {
+            AuthenticationError(crate::auth::AuthenticationError::AccountValidation(err)) => {
+                return format!("Login refused: {}", err.description());
+            }
+            AuthenticationError(err) if err.is_misconfiguration() => {
+                "Login system misconfigured... Contact the administrator"
+            }
             AuthenticationError(_) => "Authentication failed",
+            LoginRefused => "Login refused... Check the logs for more information",
+            AlreadyLoggedIn => "This user already has a running session",
             NoGraphicalEnvironment => "No graphical environment specified",
             FailedGraphicalEnvironment => "Failed booting into the graphical environment",
+            SessionScriptNotExecutable => {
+                "Session script is missing or not executable... Check the logs for more information"
+            }
+            NoInteractiveShell => "This user cannot login to a TTY shell",
+            SessionWrapperNotFound => {
+                "Session wrapper not found... Check the logs for more information"
+            }
             FailedDesktop => "Failed booting into desktop environment",
             FailedShutdown => "Failed to shutdown... Check the logs for more information",
             FailedReboot => "Failed to reboot... Check the logs for more information",
-        }
+            FailedConsoleSwitch => {
+                "Failed to switch to the console... Check the logs for more information"
+            }
+            UnexpectedFailure => {
+                "Something went wrong while logging in... Check the logs for more information"
+            }
+            TooManyFailures(instructions) => return instructions,
+            NoLogin(message) => return message,
+            SafeSessionSelected { failed, safe } => {
+                return format!("'{failed}' keeps failing to start. Selected '{safe}' instead");
+            }
+            Confirmation(ConfirmationError::Empty) => "The password cannot be empty",
+            Confirmation(ConfirmationError::TrailingWhitespace) => {
+                "The passwords differ in trailing spaces"
+            }
+            Confirmation(ConfirmationError::Mismatch) => "The passwords do not match",
+        };
+
+        message.to_string()
     }
 }
 
@@ -37,19 +98,27 @@ impl From<ErrorStatusMessage> for StatusMessage {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum InfoStatusMessage {
     LoggingIn,
     Authenticating,
+    /// Starting the session with the given name
+    StartingSession(String),
+    /// The remembered environment with the given name is no longer installed
+    MissingEnvironment(String),
 }
 
-impl From<InfoStatusMessage> for &'static str {
+impl From<InfoStatusMessage> for String {
     fn from(info: InfoStatusMessage) -> Self {
         use InfoStatusMessage::*;
 
         match info {
-            LoggingIn => "Authentication successful. Logging in...",
-            Authenticating => "Verifying credentials",
+            LoggingIn => "Authentication successful. Logging in...".to_string(),
+            Authenticating => "Verifying credentials".to_string(),
+            StartingSession(name) => format!("Authentication successful. Starting {name}..."),
+            MissingEnvironment(name) => {
+                format!("The environment '{name}' is no longer installed. Please choose another")
+            }
         }
     }
 }
@@ -66,7 +135,7 @@ pub enum StatusMessage {
     Info(InfoStatusMessage),
 }
 
-impl From<StatusMessage> for &'static str {
+impl From<StatusMessage> for String {
     fn from(msg: StatusMessage) -> Self {
         use StatusMessage::*;
 
@@ -77,21 +146,70 @@ impl From<StatusMessage> for &'static str {
     }
 }
 
+/// The severity of a status message, which determines its color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusLevel {
+    Info,
+    Success,
+    Error,
+}
+
+impl StatusLevel {
+    fn color(self, config: &StatusMessageConfig) -> Color {
+        get_color(match self {
+            Self::Info => &config.info_color,
+            Self::Success => &config.success_color,
+            Self::Error => &config.error_color,
+        })
+    }
+}
+
+/// The frames of the spinner that is shown while something is in progress
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
 impl StatusMessage {
     /// Fetch whether status is an error
     pub fn is_error(&self) -> bool {
         matches!(self, Self::Error(_))
     }
 
-    pub fn render<B: Backend>(status: Option<Self>, frame: &mut Frame<B>, area: Rect) {
+    /// Fetch the severity of the status
+    pub fn level(&self) -> StatusLevel {
+        match self {
+            Self::Error(_) => StatusLevel::Error,
+            Self::Info(InfoStatusMessage::LoggingIn | InfoStatusMessage::StartingSession(_)) => {
+                StatusLevel::Success
+            }
+            Self::Info(_) => StatusLevel::Info,
+        }
+    }
+
+    /// Fetch whether status describes something that is still in progress
+    pub fn is_in_progress(&self) -> bool {
+        matches!(
+            self,
+            Self::Info(InfoStatusMessage::Authenticating | InfoStatusMessage::StartingSession(_))
+        )
+    }
+
+    /// Render the status message. The `tick` animates the spinner of statuses that are in
+    /// progress.
+    pub fn render<B: Backend>(
+        status: Option<Self>,
+        frame: &mut Frame<B>,
+        area: Rect,
+        tick: usize,
+        config: &StatusMessageConfig,
+    ) {
         if let Some(status_message) = status {
-            let widget = Paragraph::new(<&'static str>::from(status_message.clone())).style(
-                tui::style::Style::default().fg(if status_message.is_error() {
-                    Color::Red
-                } else {
-                    Color::Yellow
-                }),
-            );
+            let mut text = String::from(status_message.clone());
+            if status_message.is_in_progress() {
+                text.push(' ');
+                text.push(SPINNER_FRAMES[tick % SPINNER_FRAMES.len()]);
+            }
+
+            let widget = Paragraph::new(text)
+                .style(Style::default().fg(status_message.level().color(config)));
 
             frame.render_widget(widget, area);
         } else {
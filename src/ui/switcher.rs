@@ -47,7 +47,8 @@ impl<T> Switcher<T> {
         self.items.len()
     }
 
-    pub fn try_select(&mut self, title: &str) {
+    /// Select the item with `title`. Returns whether such an item exists.
+    pub fn try_select(&mut self, title: &str) -> bool {
         // Only set the selected if we find a matching title
         if let Some(selected) = self
             .items
@@ -57,8 +58,10 @@ impl<T> Switcher<T> {
             .map(|(index, _)| index)
         {
             self.selected = Some(selected);
+            true
         } else {
             warn!("Failed to find selection with title: '{}'", title);
+            false
         }
     }
 
@@ -130,7 +133,12 @@ impl<T> SwitcherWidget<T> {
         }
     }
 
-    pub fn try_select(&mut self, title: &str) {
+    /// The number of items that can be selected
+    pub fn item_count(&self) -> usize {
+        self.selector.len()
+    }
+
+    pub fn try_select(&mut self, title: &str) -> bool {
         self.selector.try_select(title)
     }
 
@@ -381,6 +389,17 @@ impl<T> SwitcherWidget<T> {
         let Self { selector, .. } = &self;
         selector.current()
     }
+
+    /// Replace the content of the selected item, while keeping its title
+    pub fn set_selected_content(&mut self, content: T) {
+        let Self { selector, .. } = self;
+        if let Some(item) = selector
+            .selected
+            .and_then(|index| selector.items.get_mut(index))
+        {
+            item.content = content;
+        }
+    }
 }
 
 #[cfg(test)]
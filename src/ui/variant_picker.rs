@@ -0,0 +1,127 @@
+use tui::layout::Alignment;
+use tui::style::{Modifier, Style};
+use tui::text::{Span, Spans, Text};
+use tui::widgets::{Block, Borders, Clear, Paragraph};
+use tui::Frame;
+
+use super::session_details::centered;
+use crate::post_login::PostLoginEnvironment;
+
+/// An overlay to choose which variant of the selected environment is started
+#[derive(Clone)]
+pub struct VariantPickerWidget {
+    title: String,
+    variants: Vec<(String, PostLoginEnvironment)>,
+    selected: usize,
+}
+
+impl VariantPickerWidget {
+    /// The picker for the variants of an environment, starting at the variant that is currently
+    /// used
+    pub fn new(
+        title: String,
+        variants: Vec<(String, PostLoginEnvironment)>,
+        current: &PostLoginEnvironment,
+    ) -> Self {
+        let selected = variants
+            .iter()
+            .position(|(_, variant)| variant == current)
+            .unwrap_or(0);
+
+        Self {
+            title,
+            variants,
+            selected,
+        }
+    }
+
+    pub fn next(&mut self) {
+        if self.selected + 1 < self.variants.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// The variant that is chosen when the picker is confirmed
+    pub fn selected(&self) -> Option<&PostLoginEnvironment> {
+        self.variants.get(self.selected).map(|(_, variant)| variant)
+    }
+
+    pub fn render(&self, frame: &mut Frame<impl tui::backend::Backend>) {
+        let mut lines: Vec<Spans> = self
+            .variants
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _))| {
+                if index == self.selected {
+                    Spans::from(Span::styled(
+                        format!("> {name}"),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ))
+                } else {
+                    Spans::from(Span::raw(format!("  {name}")))
+                }
+            })
+            .collect();
+        lines.push(Spans::default());
+        lines.push(Spans::from(Span::styled(
+            "Enter to choose, Esc to keep",
+            Style::default().add_modifier(Modifier::DIM),
+        )));
+
+        let content_width = lines
+            .iter()
+            .map(Spans::width)
+            .chain([self.title.len() + 2])
+            .max()
+            .unwrap_or(0);
+        let area = centered(
+            frame.size(),
+            u16::try_from(content_width + 4).unwrap_or(u16::MAX),
+            u16::try_from(lines.len() + 2).unwrap_or(u16::MAX),
+        );
+
+        let widget = Paragraph::new(Text::from(lines))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {} ", self.title)),
+            )
+            .alignment(Alignment::Left);
+
+        // Otherwise, the form shines through the empty cells
+        frame.render_widget(Clear, area);
+        frame.render_widget(widget, area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(name: &str) -> (String, PostLoginEnvironment) {
+        (
+            name.to_string(),
+            PostLoginEnvironment::X {
+                xinitrc_path: format!("/etc/lemurs/wms/i3/{name}"),
+            },
+        )
+    }
+
+    #[test]
+    fn choose_variant() {
+        let variants = vec![variant("bare"), variant("compositor")];
+        let mut picker =
+            VariantPickerWidget::new("i3".to_string(), variants.clone(), &variants[1].1);
+        assert_eq!(picker.selected(), Some(&variants[1].1));
+
+        picker.next();
+        assert_eq!(picker.selected(), Some(&variants[1].1));
+        picker.prev();
+        picker.prev();
+        assert_eq!(picker.selected(), Some(&variants[0].1));
+    }
+}